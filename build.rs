@@ -0,0 +1,41 @@
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+// Version of skim resolved by cargo for this build, or "unknown" if cargo metadata fails
+fn skim_version() -> String {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let manifest = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("Cargo.toml");
+    let output = Command::new(cargo)
+        .args(["metadata", "--format-version", "1", "--offline"])
+        .arg("--manifest-path")
+        .arg(&manifest)
+        // resolve only packages of the target, which are downloaded for the build
+        .arg("--filter-platform")
+        .arg(env::var("TARGET").unwrap())
+        .output();
+    let metadata: Option<serde_json::Value> = output
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| serde_json::from_slice(&output.stdout).ok());
+    metadata
+        .as_ref()
+        .and_then(|metadata| metadata["packages"].as_array())
+        .and_then(|packages| packages.iter().find(|package| package["name"] == "skim"))
+        .and_then(|package| package["version"].as_str())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+// Export versions of dependencies and enabled features for `--version-detailed`
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rustc-env=SKIM_VERSION={}", skim_version());
+
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(str::to_string))
+        .map(|feature| feature.to_lowercase().replace('_', "-"))
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=SKLAUNCHER_FEATURES={}", features.join(","));
+}
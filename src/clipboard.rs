@@ -0,0 +1,183 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use regex::Regex;
+
+use crate::entry::Entry;
+use crate::exec::{resolve_command, to_local_path};
+
+lazy_static! {
+    static ref RE_SCHEME: Regex = Regex::new(r"^([A-Za-z][A-Za-z0-9+.-]*):\S+$").unwrap();
+    /// Content of the clipboard for `--open-clipboard`, or why it can't be opened
+    pub static ref CLIPBOARD: Result<Clipboard, String> =
+        read_clipboard().and_then(|text| Clipboard::new(&text));
+}
+
+/// Content of the clipboard to open with the selected entry
+pub struct Clipboard {
+    /// Argument passed to the entry like a `--open` value
+    pub arg: String,
+    /// MIME type of the file, or `x-scheme-handler/SCHEME` for URLs
+    pub mime: String,
+}
+
+impl Clipboard {
+    fn new(text: &str) -> Result<Self, String> {
+        match classify(text) {
+            Some(Target::Url(url, scheme)) => Ok(Clipboard {
+                mime: format!("x-scheme-handler/{}", scheme.to_ascii_lowercase()),
+                arg: url,
+            }),
+            Some(Target::Path(path)) => Ok(Clipboard {
+                mime: file_mime_type(&path)?,
+                arg: path.to_string_lossy().into_owned(),
+            }),
+            None => Err("clipboard does not contain a URL or a file path".to_string()),
+        }
+    }
+}
+
+// Kind of the clipboard text
+#[derive(Debug, PartialEq)]
+enum Target {
+    // URL other than `file://`, and its scheme
+    Url(String, String),
+    // local file, given as a path or a `file://` URL
+    Path(PathBuf),
+}
+
+// Tell whether the text is a URL or a file path. `file://` URLs and paths starting with `/` or
+// `~/` are paths, and a single word starting with a scheme like `https:` or `mailto:` is a URL.
+// Other text, including multiple lines, is neither.
+fn classify(text: &str) -> Option<Target> {
+    let text = text.trim();
+    if text.is_empty() || text.contains('\n') {
+        return None;
+    }
+    if let Some(rest) = text.strip_prefix("~/") {
+        let home = env::var_os("HOME")?;
+        return Some(Target::Path(Path::new(&home).join(rest)));
+    }
+    if text.starts_with('/') {
+        return Some(Target::Path(PathBuf::from(text)));
+    }
+    let scheme = RE_SCHEME.captures(text)?.get(1)?.as_str();
+    if scheme.eq_ignore_ascii_case("file") {
+        return to_local_path(text).map(|path| Target::Path(PathBuf::from(path)));
+    }
+    Some(Target::Url(text.to_string(), scheme.to_string()))
+}
+
+// Read text in the clipboard with `wl-paste` on Wayland or `xclip` on X11
+fn read_clipboard() -> Result<String, String> {
+    let args: &[&str] = if env::var_os("WAYLAND_DISPLAY").is_some() {
+        &["wl-paste", "--no-newline", "--type", "text"]
+    } else {
+        &[
+            "xclip",
+            "-selection",
+            "clipboard",
+            "-out",
+            "-target",
+            "UTF8_STRING",
+        ]
+    };
+    if resolve_command(args[0]).is_none() {
+        return Err(format!("{} is not found in $PATH", args[0]));
+    }
+    let output = Command::new(args[0])
+        .args(&args[1..])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| format!("failed to run {}: {}", args[0], e))?;
+    if !output.status.success() {
+        return Err("clipboard is empty or has no text".to_string());
+    }
+    String::from_utf8(output.stdout).map_err(|_| "clipboard content is not text".to_string())
+}
+
+// Detect the MIME type of an existing file with `file`, or `xdg-mime` if it's not installed
+fn file_mime_type(path: &Path) -> Result<String, String> {
+    if path.is_dir() {
+        return Ok("inode/directory".to_string());
+    }
+    if !path.exists() {
+        return Err(format!("file not found: {}", path.display()));
+    }
+    let commands: [&[&str]; 2] = [
+        &["file", "--brief", "--mime-type", "--"],
+        &["xdg-mime", "query", "filetype"],
+    ];
+    commands
+        .iter()
+        .find_map(|args| {
+            let output = Command::new(args[0])
+                .args(&args[1..])
+                .arg(path)
+                .stdin(Stdio::null())
+                .stderr(Stdio::null())
+                .output()
+                .ok()?;
+            let mime = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            (output.status.success() && mime.contains('/')).then_some(mime)
+        })
+        .ok_or_else(|| format!("failed to detect the type of {}", path.display()))
+}
+
+/// Check if the entry can open the clipboard content of `--open-clipboard`
+pub fn handles_clipboard(entry: &Entry) -> bool {
+    match &*CLIPBOARD {
+        Ok(clipboard) => entry.handles_mime(&clipboard.mime),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_told_by_scheme() {
+        let url = |url: &str, scheme: &str| Some(Target::Url(url.to_string(), scheme.to_string()));
+        assert_eq!(
+            classify("https://example.com/a?b=c"),
+            url("https://example.com/a?b=c", "https")
+        );
+        assert_eq!(
+            classify("  mailto:user@example.com\n"),
+            url("mailto:user@example.com", "mailto")
+        );
+        assert_eq!(
+            classify("HTTP://EXAMPLE.COM"),
+            url("HTTP://EXAMPLE.COM", "HTTP")
+        );
+    }
+
+    #[test]
+    fn paths_and_file_urls_are_paths() {
+        let path = |path: &str| Some(Target::Path(PathBuf::from(path)));
+        assert_eq!(classify("/tmp/a b.txt"), path("/tmp/a b.txt"));
+        assert_eq!(classify("file:///tmp/a%20b.txt"), path("/tmp/a b.txt"));
+        let home = env::var_os("HOME").unwrap();
+        assert_eq!(
+            classify("~/notes.md"),
+            Some(Target::Path(Path::new(&home).join("notes.md")))
+        );
+    }
+
+    #[test]
+    fn plain_text_is_neither() {
+        for text in [
+            "",
+            "hello world",
+            "notes.md",
+            "see https://example.com",
+            "https://example.com\nhttps://example.org",
+            "/tmp/a\n/tmp/b",
+        ] {
+            assert_eq!(classify(text), None, "{:?}", text);
+        }
+    }
+}
@@ -1,9 +1,10 @@
 use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::env;
 use std::fmt::Write as FmtWrite;
 use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
 use clap::Parser;
@@ -11,20 +12,55 @@ use indexmap::map::IndexMap;
 use lenient_bool::LenientBool;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use skim::field::parse_matching_fields;
 use skim::prelude::*;
+use tuikit::attr::{Attr, Effect};
 
+use crate::exec::resolve_command;
 use crate::history::{load_history, save_history};
-use crate::options::{AccentColor, Cli};
+use crate::icon::resolve_icon;
+use crate::options::{parse_glob, AccentColor, Cli, PreviewTheme, SortBy};
+use crate::preview::bin_description;
 
 lazy_static! {
-    static ref RE_WHATIS: Regex = Regex::new(r"(?m)^.*?\s+-\s+").unwrap();
-    pub static ref OPTIONS: Cli = Cli::parse();
-    static ref MATCH_GENERIC_NAME: bool = OPTIONS.match_generic_name;
-    static ref SHOW_GENERIC_NAME: bool = OPTIONS.show_generic_name;
+    static ref RE_CONTROL: Regex =
+        Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b[@-_]?|[\x00-\x1f\x7f]").unwrap();
+    static ref RE_SPACE: Regex = Regex::new(r" +").unwrap();
+    static ref RE_PLACEHOLDER: Regex = Regex::new(r"\{ *-?[0-9.,cq+n]*? *}").unwrap();
+    static ref PREVIEW_COMMAND: Option<String> = get_preview_command();
+    // tests run with the default options instead of the arguments of the test runner
+    pub static ref OPTIONS: Cli = if cfg!(test) {
+        Cli::parse_from(["sklauncher"])
+    } else {
+        Cli::parse()
+    };
+    static ref GENERIC_SEPARATOR: &'static str =
+        OPTIONS.generic_separator.as_deref().unwrap_or(", ");
     static ref ACCENT_COLOR: u8 = get_accent_color();
+    static ref PREVIEW_THEME: PreviewTheme = get_preview_theme();
+    static ref NOW: u64 = now();
+    static ref EXCLUDE: Vec<Regex> = get_exclude_patterns();
+    static ref BIN_PATHS: Vec<PathBuf> = get_paths(&OPTIONS);
+    static ref ENV_PATHS: Vec<PathBuf> = env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).collect())
+        .unwrap_or_default();
 }
 
-fn get_app_dirs() -> Vec<PathBuf> {
+// Keys only used by autostart entries
+const AUTOSTART_KEYS: [&str; 4] = [
+    "X-GNOME-Autostart-enabled",
+    "X-GNOME-Autostart-Phase",
+    "X-KDE-autostart-after",
+    "X-KDE-autostart-phase",
+];
+
+// Extensions removed from bin entry names by `--strip-extension`
+const SCRIPT_EXTENSIONS: [&str; 4] = [".sh", ".py", ".pl", ".rb"];
+
+// Maximum depth of subdirectories to descend into under an application directory
+const MAX_DESKTOP_DIR_DEPTH: usize = 16;
+
+pub fn get_app_dirs() -> Vec<PathBuf> {
     let app_dirs_base = xdg::BaseDirectories::with_prefix("applications").unwrap();
     let mut app_dirs = vec![app_dirs_base.get_data_home()];
     app_dirs.extend(app_dirs_base.get_data_dirs());
@@ -34,7 +70,8 @@ fn get_app_dirs() -> Vec<PathBuf> {
         .collect::<Vec<PathBuf>>()
 }
 
-fn get_paths() -> Vec<PathBuf> {
+// Directories to search for executables: `$PATH`, then `--bin-dir` and `~/.local/bin`
+fn get_paths(options: &Cli) -> Vec<PathBuf> {
     let mut result: Vec<PathBuf> = Vec::new();
     match env::var_os("PATH") {
         Some(paths) => {
@@ -46,10 +83,39 @@ fn get_paths() -> Vec<PathBuf> {
         }
         None => eprintln!("$PATH is not defined in the environment"),
     }
+    for path in options.bin_dir.iter() {
+        if path.is_dir() {
+            result.push(path.clone());
+        } else {
+            eprintln!("Bin directory not found: {}", path.display());
+        }
+    }
+    if options.include_local_bin {
+        if let Some(home) = env::var_os("HOME") {
+            let local_bin = Path::new(&home).join(".local/bin");
+            if local_bin.is_dir() && !result.contains(&local_bin) {
+                result.push(local_bin);
+            }
+        }
+    }
     result
 }
 
-fn get_mtime(file: &PathBuf) -> f64 {
+// Check that `file` resolves to an executable regular file.
+// `fs::metadata` follows symlinks, so dangling links left by removed packages are rejected.
+pub fn is_executable_file(file: &Path) -> bool {
+    match fs::metadata(file) {
+        Ok(meta) => meta.is_file() && meta.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+// Remove escape sequences and control characters from a user-facing desktop entry value
+fn sanitize(value: &str) -> String {
+    RE_CONTROL.replace_all(value, "").into_owned()
+}
+
+fn get_mtime(file: &Path) -> f64 {
     fs::metadata(file)
         .expect("Failed to check metadata")
         .modified()
@@ -59,21 +125,98 @@ fn get_mtime(file: &PathBuf) -> f64 {
         .as_secs_f64()
 }
 
+// Append each line of `content` on its own line with SGR parameters `sgr`,
+// resetting attributes at the end of each line
+fn write_reset_lines(text: &mut String, content: &str, sgr: &str) {
+    for line in content.lines() {
+        write!(text, "\n\x1b[{}m{}\x1b[m", sgr, line).unwrap();
+    }
+}
+
+/// Current time as seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Entry {
-    path: String,
+    #[serde(default)]
+    pub path: String,
     mtime: Option<f64>,
-    name: String,
+    pub name: String,
     pub exec: String,
     generic_name: Option<String>,
     comment: Option<String>,
+    startup_wm_class: Option<String>,
+    icon: Option<String>,
+    #[serde(default)]
+    keywords: Vec<String>,
+    #[serde(default)]
+    categories: Vec<String>,
+    // `None` for entries cached before `MimeType` was read, which are loaded again
+    mime_types: Option<Vec<String>>,
+    // `None` for entries cached before `Implements` was read, which are loaded again
+    implements: Option<Vec<String>>,
+    #[serde(default)]
     pub terminal: bool,
+    #[serde(default)]
+    pub prefers_non_default_gpu: bool,
+    #[serde(default)]
     pub desktop: bool,
+    #[serde(default)]
     pub count: u32,
+    pub last_used: Option<u64>,
+    pub last_error: Option<String>,
+    action: Option<String>,
+    #[serde(default)]
+    actions: Vec<String>,
+    #[serde(skip)]
+    matching_ranges: Vec<(usize, usize)>,
 }
 
 type EntryMap = IndexMap<String, Entry>;
 
+// Layout of `--entries-file` in TOML: a `[[entry]]` table for each entry
+#[derive(Deserialize)]
+struct EntriesFile {
+    #[serde(default)]
+    entry: Vec<Entry>,
+}
+
+// Command line of this process, used to run a child process rendering a preview
+fn get_preview_command() -> Option<String> {
+    let exe = env::current_exe()
+        .ok()?
+        .into_os_string()
+        .into_string()
+        .ok()?;
+    let mut args = vec![exe];
+    for arg in env::args_os().skip(1) {
+        args.push(arg.into_string().ok()?);
+    }
+    Some(shlex::join(args.iter().map(String::as_str)))
+}
+
+// Replace `{}` in a preview command of `--bin-preview-command` or `--desktop-preview-command`
+// with the quoted path of the entry and `{name}` with its file name. Other placeholders are
+// left to skim.
+fn expand_preview_command(command: &str, path: &str) -> String {
+    let name = Path::new(path).file_name().unwrap_or_default();
+    command
+        .replace("{name}", &shlex::quote(&name.to_string_lossy()))
+        .replace("{}", &shlex::quote(path))
+}
+
+/// Print the preview of the executable for `--render-preview`
+pub fn print_bin_preview(path: &str) {
+    let history: EntryMap = load_history();
+    let entry = load_bin_entry(Path::new(path), &history);
+    print!("{}", entry.preview_text());
+}
+
 fn get_accent_color() -> u8 {
     match OPTIONS.accent_color.unwrap_or(AccentColor::Magenta) {
         AccentColor::Black => 0,
@@ -87,6 +230,16 @@ fn get_accent_color() -> u8 {
     }
 }
 
+// Fill unset colors of `--preview-theme` with defaults
+fn get_preview_theme() -> PreviewTheme {
+    let theme = OPTIONS.preview_theme.clone().unwrap_or_default();
+    PreviewTheme {
+        name: theme.name.or_else(|| Some(format!("3{}", *ACCENT_COLOR))),
+        meta: theme.meta.or_else(|| Some("2".to_string())),
+        ..theme
+    }
+}
+
 impl Entry {
     pub fn new() -> Self {
         Entry {
@@ -96,257 +249,1810 @@ impl Entry {
             exec: "".to_string(),
             generic_name: None,
             comment: None,
+            startup_wm_class: None,
+            icon: None,
+            keywords: Vec::new(),
+            categories: Vec::new(),
+            mime_types: None,
+            implements: None,
             terminal: false,
+            prefers_non_default_gpu: false,
             desktop: false,
             count: 0,
+            last_used: None,
+            last_error: None,
+            action: None,
+            actions: Vec::new(),
+            matching_ranges: Vec::new(),
+        }
+    }
+
+    /// Set byte ranges of the match text which can be matched, so that a query never matches
+    /// across the separators between the name and other fields, nor outside the words of the
+    /// name selected by `--match-nth`
+    pub fn init_matching_ranges(&mut self) {
+        self.matching_ranges.clear();
+        let fields = self.match_fields(&OPTIONS);
+        if OPTIONS.match_nth.is_empty() && fields.is_empty() {
+            return;
+        }
+        let mut ranges = if OPTIONS.match_nth.is_empty() {
+            vec![(0, self.name.len())]
+        } else {
+            // words of the name selected by `--match-nth`, or an empty range if there are none
+            let ranges = parse_matching_fields(&RE_SPACE, &self.name, &OPTIONS.match_nth);
+            if ranges.is_empty() {
+                vec![(0, 0)]
+            } else {
+                ranges
+            }
+        };
+        let mut start = self.name.len();
+        for field in fields {
+            start += GENERIC_SEPARATOR.len();
+            ranges.push((start, start + field.len()));
+            start += field.len();
+        }
+        self.matching_ranges = ranges;
+    }
+
+    // Fields of desktop entries matched after the name: the generic name with
+    // `--match-generic-name`, and also the comment, keywords and categories with `--match-all`
+    fn match_fields(&self, options: &Cli) -> Vec<&str> {
+        let mut fields = Vec::new();
+        if !self.desktop {
+            return fields;
+        }
+        if options.match_generic_name || options.match_all {
+            fields.extend(self.generic_name.as_deref());
+        }
+        if options.match_all {
+            fields.extend(self.comment.as_deref());
+            fields.extend(self.keywords.iter().map(String::as_str));
+            fields.extend(self.categories.iter().map(String::as_str));
         }
+        fields
+    }
+
+    // Copy usage statistics from the entry of the previous run
+    fn restore_history(&mut self, prev: &Entry) {
+        self.count = prev.count;
+        self.last_used = prev.last_used;
+        self.last_error = prev.last_error.clone();
+    }
+
+    // Launch count weighted by how recently the entry was last used
+    fn frecency(&self) -> f64 {
+        let weight = match self.last_used {
+            Some(last_used) => match NOW.saturating_sub(last_used) {
+                age if age < 4 * 60 * 60 => 4.0,
+                age if age < 24 * 60 * 60 => 2.0,
+                age if age < 7 * 24 * 60 * 60 => 1.0,
+                age if age < 30 * 24 * 60 * 60 => 0.5,
+                _ => 0.25,
+            },
+            None => 0.25,
+        };
+        self.count as f64 * weight
     }
 }
 
-impl SkimItem for Entry {
-    fn text(&self) -> Cow<str> {
+impl Entry {
+    /// Key of the entry in entry maps: the file path, followed by `#ACTION` for desktop actions
+    pub fn key(&self) -> String {
+        match &self.action {
+            Some(action) => format!("{}#{}", self.path, action),
+            None => self.path.clone(),
+        }
+    }
+
+    /// Desktop file ID for desktop entries (path under the applications directory with `/`
+    /// replaced by `-`), or the full path for bin entries
+    pub fn id(&self) -> String {
         if self.desktop {
-            if !*MATCH_GENERIC_NAME {
-                return Cow::Borrowed(&self.name);
+            desktop_id(&self.path)
+        } else {
+            self.path.clone()
+        }
+    }
+
+    /// Check if the entry is the application of a desktop file, not one of its actions or an
+    /// entry of `--entries-file`
+    pub fn is_desktop_app(&self) -> bool {
+        self.desktop && self.action.is_none()
+    }
+
+    /// Check if the entry is a handler of the MIME type by its `MimeType` key, where a type like
+    /// `image/*` matches all subtypes
+    pub fn handles_mime(&self, mime: &str) -> bool {
+        let major = mime.split('/').next().unwrap_or_default();
+        self.mime_types.iter().flatten().any(|handled| {
+            handled.eq_ignore_ascii_case(mime)
+                || handled
+                    .strip_suffix("/*")
+                    .is_some_and(|m| m.eq_ignore_ascii_case(major))
+        })
+    }
+
+    /// Check if the entry declares the D-Bus interface in its `Implements` key
+    pub fn implements(&self, iface: &str) -> bool {
+        self.implements.iter().flatten().any(|i| i == iface)
+    }
+
+    // Check that the cached entry has all keys read from desktop files by this version
+    fn has_all_keys(&self) -> bool {
+        self.mime_types.is_some() && self.implements.is_some()
+    }
+
+    /// File of the icon of the entry, resolved from an absolute path or an icon name
+    pub fn icon_path(&self) -> Option<PathBuf> {
+        self.icon.as_deref().and_then(resolve_icon)
+    }
+
+    /// Check if the entry is listed in `--entries-file`
+    pub fn is_listed(&self) -> bool {
+        OPTIONS
+            .entries_file
+            .as_ref()
+            .is_some_and(|file| self.path == file.to_string_lossy())
+    }
+
+    // Text of the preview window with ANSI colors
+    fn preview_text(&self) -> String {
+        // every segment is followed by a reset so that stray attributes can't bleed into the rest
+        let mut text = String::new();
+        if let Some(error) = &self.last_error {
+            writeln!(text, "\x1b[31mLast launch failed: {}\x1b[m", error).unwrap();
+        }
+        let theme = &*PREVIEW_THEME;
+        let sgr = |color: &Option<String>| color.clone().unwrap_or_default();
+        write!(text, "\x1b[{}m{}\x1b[m", sgr(&theme.name), self.name).unwrap();
+        if self.desktop {
+            if let Some(gname) = &self.generic_name {
+                write!(text, " | \x1b[{}m{}\x1b[m", sgr(&theme.generic), gname).unwrap();
+            }
+            let comment = self.comment.as_ref();
+            if let Some(comment) = comment.filter(|_| !OPTIONS.no_comment_preview) {
+                write_reset_lines(&mut text, comment, &sgr(&theme.comment));
+            }
+            if let Some(wm_class) = &self.startup_wm_class {
+                let line = format!("WM class: {}", wm_class);
+                write_reset_lines(&mut text, &line, &sgr(&theme.meta));
             }
-            match &self.generic_name {
-                Some(gname) => Cow::Owned(format!("{}, {}", self.name, gname)),
-                None => Cow::Borrowed(&self.name),
+            if let Some(path) = self.icon_path() {
+                let line = format!("Icon: {}", path.display());
+                write_reset_lines(&mut text, &line, &sgr(&theme.meta));
             }
         } else {
-            Cow::Borrowed(&self.name)
+            if !OPTIONS.no_comment_preview {
+                let path = Path::new(&self.path);
+                let filename = path.file_name().unwrap_or_default().to_string_lossy();
+                if let Some(description) = bin_description(&self.path, &filename) {
+                    write_reset_lines(&mut text, &description, &sgr(&theme.comment));
+                }
+            }
+            let line = format!("Path: {}", self.path);
+            write_reset_lines(&mut text, &line, &sgr(&theme.meta));
+            // executables of the same name in other bin directories, which are found before
+            // (shadow) or after (are shadowed by) this one in the order of lookup
+            let real = fs::canonicalize(&self.path).ok();
+            let mut before = true;
+            for (location, real_location) in bin_locations(&self.path, &BIN_PATHS) {
+                if real.as_ref() == Some(&real_location) {
+                    before = false;
+                    continue;
+                }
+                let label = if before { "Shadowed by" } else { "Shadows" };
+                let line = format!("{}: {}", label, location.display());
+                write_reset_lines(&mut text, &line, &sgr(&theme.meta));
+            }
         }
+        text.push_str("\x1b[m");
+        text
     }
 
-    fn display<'a>(&self, context: DisplayContext<'a>) -> AnsiString<'a> {
-        // Shift highlight char position by icon width
-        let icon = if self.desktop {
+    // Build the label shown after the icon.
+    // Returns the label, the char position of the name in it (if shown), and the number of
+    // matched chars that can be highlighted from that position.
+    fn label(&self, options: &Cli) -> (String, Option<usize>, usize) {
+        if let Some(template) = &options.label_format {
+            let name_len = self.name.chars().count();
+            let (label, name_start) = render_label(
+                template,
+                &self.name,
+                self.generic_name.as_deref().unwrap_or(""),
+                self.comment.as_deref().unwrap_or(""),
+            );
+            return (label, name_start, name_len);
+        }
+        if options.show_generic_name {
+            if let Some(gname) = &self.generic_name {
+                let separator = options.generic_separator.as_deref().unwrap_or(", ");
+                let label = format!("{}{}{}", self.name, separator, gname);
+                return (label, Some(0), usize::MAX);
+            }
+        }
+        (self.name.clone(), Some(0), usize::MAX)
+    }
+
+    // Text matched against the query: the name followed by the fields matched by the options
+    fn match_text(&self, options: &Cli) -> Cow<'_, str> {
+        let fields = self.match_fields(options);
+        if fields.is_empty() {
+            return Cow::Borrowed(&self.name);
+        }
+        // use the same separator as display so highlight positions of the generic name line up
+        let separator = options.generic_separator.as_deref().unwrap_or(", ");
+        let mut text = self.name.clone();
+        for field in fields {
+            text.push_str(separator);
+            text.push_str(field);
+        }
+        Cow::Owned(text)
+    }
+
+    // Displayed line of the entry in the list by the options
+    fn render<'a>(&self, context: DisplayContext<'a>, options: &Cli) -> AnsiString<'a> {
+        // Shift highlight char position by the icon. Highlights are given in chars, not in
+        // display columns, so the shift is the number of chars even for wide icons.
+        let icon = if options.flatten {
+            ""
+        } else if self.desktop {
             "\u{f108}  "
         } else {
             "\u{f120}  "
         };
-        let icon_shift: usize = 3;
-        let text;
-        if *SHOW_GENERIC_NAME {
-            match &self.generic_name {
-                Some(gname) => text = format!("{}{}, {}", icon, self.name, gname),
-                None => text = format!("{}{}", icon, self.name),
+        let icon_shift = icon.chars().count();
+        let (label, name_start, limit) = self.label(options);
+        let mut text = format!("{}{}", icon, label);
+        let mut fragments = match name_start {
+            // matches in fields which are not shown are not highlighted
+            Some(pos) => {
+                let limit = limit.min(label.chars().count().saturating_sub(pos));
+                highlight_fragments(&context, icon_shift + pos, limit)
             }
+            None => vec![],
+        };
+        // suffixes come after the label, so highlights of the name are not shifted
+        let dim = Attr {
+            effect: Effect::DIM,
+            ..Attr::default()
+        };
+        if options.show_count && self.count > 0 {
+            let start = text.chars().count();
+            write!(text, "  \u{d7}{}", self.count).unwrap();
+            fragments.push((dim, (start as u32, text.chars().count() as u32)));
+        }
+        if options.show_id {
+            let start = text.chars().count();
+            write!(text, "  {}", self.id()).unwrap();
+            fragments.push((dim, (start as u32, text.chars().count() as u32)));
+        }
+        AnsiString::new_string(text, fragments)
+    }
+}
+
+// Convert matched positions in the match text to highlighted char ranges in the display text.
+// Positions are shifted by `shift`, and positions from `limit` on are not highlighted.
+fn highlight_fragments(
+    context: &DisplayContext,
+    shift: usize,
+    limit: usize,
+) -> Vec<(Attr, (u32, u32))> {
+    // highlight chars from `s` to `e` (exclusive)
+    let range = |s: usize, e: usize| {
+        let e = e.min(limit);
+        if s >= e {
+            vec![]
         } else {
-            text = format!("{}{}", icon, self.name);
+            vec![(
+                context.highlight_attr,
+                ((s + shift) as u32, (e + shift) as u32),
+            )]
+        }
+    };
+    match context.matches {
+        Matches::CharIndices(indices) => indices
+            .iter()
+            .filter(|&&i| i < limit)
+            .map(|&i| {
+                (
+                    context.highlight_attr,
+                    ((i + shift) as u32, (1 + i + shift) as u32),
+                )
+            })
+            .collect(),
+        Matches::CharRange(s, e) => range(s, e),
+        Matches::ByteRange(start, end) => {
+            let s = context.text[..start].chars().count();
+            let e = s + context.text[start..end].chars().count();
+            range(s, e)
         }
-        match context.matches {
-            Matches::CharIndices(indices) => {
-                if indices.is_empty() {
-                    return AnsiString::new_string(text, vec![]);
+        Matches::None => vec![],
+    }
+}
+
+enum LabelToken<'a> {
+    Literal(&'a str),
+    Name,
+    Field(&'a str),
+}
+
+// Render `--label-format` template.
+// Literals next to an empty field are dropped so that no dangling separators are left: the one
+// before the field, and the one after it if nothing is shown before or after the field.
+// Returns the rendered label and the char position of `{name}` in it.
+fn render_label(
+    template: &str,
+    name: &str,
+    generic: &str,
+    comment: &str,
+) -> (String, Option<usize>) {
+    let mut tokens: Vec<LabelToken> = Vec::new();
+    let mut rest = template;
+    while !rest.is_empty() {
+        let placeholder = ["{name}", "{generic}", "{comment}"]
+            .iter()
+            .filter_map(|p| rest.find(p).map(|pos| (pos, *p)))
+            .min();
+        match placeholder {
+            Some((pos, p)) => {
+                if pos > 0 {
+                    tokens.push(LabelToken::Literal(&rest[..pos]));
                 }
-                let fragments = indices
-                    .into_iter()
-                    .map(|&i| {
-                        (
-                            context.highlight_attr,
-                            ((i + icon_shift) as u32, (1 + i + icon_shift) as u32),
-                        )
-                    })
-                    .collect();
-                AnsiString::new_string(text, fragments)
+                tokens.push(match p {
+                    "{name}" => LabelToken::Name,
+                    "{generic}" => LabelToken::Field(generic),
+                    _ => LabelToken::Field(comment),
+                });
+                rest = &rest[pos + p.len()..];
             }
-            Matches::CharRange(s, e) => {
-                let empty = s == e;
-                let start = if empty { s } else { s + icon_shift };
-                let end = if empty { e } else { e + icon_shift + 1 };
-                AnsiString::new_string(
-                    text,
-                    vec![(context.highlight_attr, (start as u32, end as u32))],
-                )
+            None => {
+                tokens.push(LabelToken::Literal(rest));
+                rest = "";
             }
-            Matches::ByteRange(start, end) => {
-                let s = context.text[start..end].chars().count();
-                let e = s + context.text[start..end].chars().count();
-                let empty = s == e;
-                let start = if empty { s } else { s + icon_shift };
-                let end = if empty { e } else { e + icon_shift + 1 };
-                AnsiString::new_string(
-                    text,
-                    vec![(context.highlight_attr, (start as u32, end as u32))],
-                )
+        }
+    }
+
+    let is_empty_field =
+        |token: Option<&LabelToken>| matches!(token, Some(LabelToken::Field(v)) if v.is_empty());
+    let is_shown = |token: &LabelToken| match token {
+        LabelToken::Literal(_) => false,
+        LabelToken::Name => true,
+        LabelToken::Field(value) => !value.is_empty(),
+    };
+    let mut label = String::new();
+    let mut name_start = None;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            LabelToken::Literal(lit) => {
+                let prev = if i > 0 { tokens.get(i - 1) } else { None };
+                let at_start = !tokens[..i].iter().any(is_shown);
+                let at_end = !tokens[i + 1..].iter().any(is_shown);
+                let dropped = is_empty_field(tokens.get(i + 1))
+                    || (is_empty_field(prev) && (at_start || at_end));
+                if !dropped {
+                    label.push_str(lit);
+                }
             }
-            Matches::None => AnsiString::new_string(text, vec![]),
+            LabelToken::Name => {
+                name_start.get_or_insert(label.chars().count());
+                label.push_str(name);
+            }
+            LabelToken::Field(value) => label.push_str(value),
         }
     }
 
-    fn output(&self) -> Cow<str> {
-        Cow::Borrowed(&self.path)
+    let trimmed = label.trim_start();
+    let offset = label.chars().count() - trimmed.chars().count();
+    let name_start = name_start.map(|pos| pos.saturating_sub(offset));
+    (trimmed.trim_end().to_string(), name_start)
+}
+
+impl SkimItem for Entry {
+    fn text(&self) -> Cow<'_, str> {
+        self.match_text(&OPTIONS)
+    }
+
+    fn display<'a>(&self, context: DisplayContext<'a>) -> AnsiString<'a> {
+        self.render(context, &OPTIONS)
+    }
+
+    fn output(&self) -> Cow<'_, str> {
+        Cow::Owned(self.key())
+    }
+
+    fn get_matching_ranges(&self) -> Option<&[(usize, usize)]> {
+        if self.matching_ranges.is_empty() {
+            None
+        } else {
+            Some(&self.matching_ranges)
+        }
     }
 
     fn preview(&self, _context: PreviewContext) -> ItemPreview {
-        let mut text = String::new();
-        write!(text, "\x1b[3{}m{}\x1b[m", *ACCENT_COLOR, self.name).unwrap();
-        if self.desktop {
-            match &self.generic_name {
-                Some(gname) => write!(text, " | {}", gname).unwrap(),
-                None => {}
+        let source_command = if self.desktop {
+            // entries of `--entries-file` have no desktop file
+            OPTIONS
+                .desktop_preview_command
+                .as_ref()
+                .filter(|_| !self.is_listed())
+        } else {
+            OPTIONS.bin_preview_command.as_ref()
+        };
+        if let Some(command) = source_command {
+            return ItemPreview::Command(expand_preview_command(command, &self.path));
+        }
+        // descriptions of executables come from external commands which can be slow, so they
+        // are rendered by a child process which skim runs without blocking the UI
+        if !self.desktop
+            && OPTIONS.render_preview.is_none()
+            && !OPTIONS.bin_preview.is_empty()
+            && !OPTIONS.no_comment_preview
+        {
+            if let Some(prefix) = &*PREVIEW_COMMAND {
+                let cmd = format!("{} --render-preview {}", prefix, shlex::quote(&self.path));
+                // skim replaces placeholders like `{}` in preview commands
+                if !RE_PLACEHOLDER.is_match(&cmd) {
+                    return ItemPreview::Command(cmd);
+                }
             }
-            match &self.comment {
-                Some(comment) => write!(text, "\n{}", comment).unwrap(),
-                None => {}
+        }
+        ItemPreview::AnsiText(self.preview_text())
+    }
+}
+
+/// Compare entries by frecency (launch count weighted by recency), higher first
+pub fn frecency_cmp(v1: &Entry, v2: &Entry) -> Ordering {
+    v2.frecency().total_cmp(&v1.frecency())
+}
+
+/// Compare entries by name as the final tiebreak, unless disabled by `--no-name-tiebreak`.
+/// Names are compared ignoring case unless `--case-sensitive-sort` is given, and names equal
+/// ignoring case are ordered case-sensitively.
+pub fn name_cmp(v1: &Entry, v2: &Entry, options: &Cli) -> Ordering {
+    if options.no_name_tiebreak {
+        Ordering::Equal
+    } else if options.case_sensitive_sort {
+        v1.name.cmp(&v2.name)
+    } else {
+        v1.name
+            .to_lowercase()
+            .cmp(&v2.name.to_lowercase())
+            .then_with(|| v1.name.cmp(&v2.name))
+    }
+}
+
+/// Comparator used to order entries fed to skim.
+///
+/// Entries are ordered by the `--sort-by` criterion first (higher count/frecency first), then
+/// desktop entries come before bin entries, and finally entries are sorted by name.
+fn entry_cmp(v1: &Entry, v2: &Entry, options: &Cli) -> Ordering {
+    let primary = match options.sort_by.unwrap_or(SortBy::Count) {
+        SortBy::Count => v2.count.cmp(&v1.count),
+        SortBy::Frecency => frecency_cmp(v1, v2),
+        SortBy::Name => Ordering::Equal,
+    };
+    seed_cmp(v1, v2, primary, options)
+}
+
+// Order of entries fed to skim given the order by the primary criterion
+fn seed_cmp(v1: &Entry, v2: &Entry, primary: Ordering, options: &Cli) -> Ordering {
+    // with `--group`, all desktop entries come before executables regardless of the criterion
+    let group = if options.group {
+        v2.desktop.cmp(&v1.desktop)
+    } else {
+        Ordering::Equal
+    };
+    group
+        .then(primary)
+        .then_with(|| v2.desktop.cmp(&v1.desktop))
+        .then_with(|| name_cmp(v1, v2, options))
+}
+
+// Ranges of launch counts and last used times of entries, used to normalize them
+struct HistoryScale {
+    max_count: u32,
+    first_used: u64,
+    last_used: u64,
+}
+
+impl HistoryScale {
+    fn new<'a>(entries: impl Iterator<Item = &'a Entry>) -> Self {
+        let mut scale = HistoryScale {
+            max_count: 0,
+            first_used: u64::MAX,
+            last_used: 0,
+        };
+        for entry in entries {
+            scale.max_count = scale.max_count.max(entry.count);
+            if let Some(used) = entry.last_used {
+                scale.first_used = scale.first_used.min(used);
+                scale.last_used = scale.last_used.max(used);
             }
+        }
+        scale
+    }
+
+    // Blend of the launch count and the last used time, each normalized to 0.0-1.0,
+    // weighted by `--history-weight`
+    fn score(&self, entry: &Entry, weight: f64) -> f64 {
+        let count = if self.max_count == 0 {
+            0.0
         } else {
-            let output = Command::new("whatis")
-                .arg("--long")
-                .arg(&self.path)
-                .output()
-                .expect(&format!("Failed to read man of command: {}", self.path));
-            if output.status.success() {
-                let comment = String::from_utf8(output.stdout).unwrap();
-                write!(text, "\n{}", RE_WHATIS.replace_all(&comment, "")).unwrap();
+            entry.count as f64 / self.max_count as f64
+        };
+        // entries used the earliest still score above entries never used
+        let recency = match entry.last_used {
+            Some(used) => {
+                (used - self.first_used + 1) as f64 / (self.last_used - self.first_used + 1) as f64
             }
-        }
-        ItemPreview::AnsiText(text)
+            None => 0.0,
+        };
+        (1.0 - weight) * count + weight * recency
     }
 }
 
-pub fn entry_cmp(_k1: &String, v1: &Entry, _k2: &String, v2: &Entry) -> Ordering {
-    v1.name.cmp(&v2.name)
+/// Sort entries fed to skim, by `--history-weight` if given, otherwise by `entry_cmp`
+pub fn sort_entries(entries: &mut EntryMap, options: &Cli) {
+    match options.history_weight {
+        Some(weight) => {
+            let scale = HistoryScale::new(entries.values());
+            entries.sort_by(|_k1, v1, _k2, v2| {
+                let primary = scale.score(v2, weight).total_cmp(&scale.score(v1, weight));
+                seed_cmp(v1, v2, primary, options)
+            });
+        }
+        None => entries.sort_by(|_k1, v1, _k2, v2| entry_cmp(v1, v2, options)),
+    }
 }
 
-pub fn load_bin_entries(history: &EntryMap) -> EntryMap {
+pub fn load_bin_entries(bin_dirs: &[PathBuf], history: &EntryMap) -> EntryMap {
     let mut result: EntryMap = IndexMap::new();
-    let paths = get_paths();
-    for dir in paths.iter() {
-        let mut entries: EntryMap = IndexMap::new();
+    for dir in bin_dirs.iter() {
         for file in dir
             .read_dir()
             .unwrap()
             .map(|f| f.expect("Failed to read file").path())
         {
-            if !file.is_file() {
+            if !is_executable_file(&file) {
                 continue;
             }
-            entries.insert(
+            result.insert(
                 file.to_str().unwrap().to_string(),
                 load_bin_entry(&file, history),
             );
         }
-        entries.sort_by(entry_cmp);
-        result.extend(entries);
     }
     result
 }
 
-fn load_bin_entry(file: &PathBuf, history: &EntryMap) -> Entry {
+// Executable files of the same name as `path` in the bin directories in the order of lookup,
+// with their canonical paths. Files reached through other directories, like `/bin` linked to
+// `/usr/bin`, are listed once by the first path.
+fn bin_locations(path: &str, bin_dirs: &[PathBuf]) -> Vec<(PathBuf, PathBuf)> {
+    let filename = match Path::new(path).file_name() {
+        Some(filename) => filename,
+        None => return Vec::new(),
+    };
+    let mut locations: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for file in bin_dirs.iter().map(|dir| dir.join(filename)) {
+        if !is_executable_file(&file) {
+            continue;
+        }
+        let real = match fs::canonicalize(&file) {
+            Ok(real) => real,
+            Err(_) => continue,
+        };
+        if !locations.iter().any(|(_, r)| *r == real) {
+            locations.push((file, real));
+        }
+    }
+    locations
+}
+
+// Remove a known script extension from a file name, keeping names which would become empty
+fn strip_script_extension(filename: &str) -> &str {
+    SCRIPT_EXTENSIONS
+        .iter()
+        .find_map(|ext| filename.strip_suffix(ext))
+        .filter(|stem| !stem.is_empty())
+        .unwrap_or(filename)
+}
+
+fn load_bin_entry(file: &Path, history: &EntryMap) -> Entry {
     let mut entry = Entry::new();
     let filestr = file.to_str().unwrap().to_string();
     let filename = file.file_name().unwrap().to_str().unwrap().to_string();
     if let Some(e) = history.get(&filestr) {
-        entry.count = e.count;
+        entry.restore_history(e);
     }
     entry.path = filestr;
-    entry.name = filename.clone();
-    entry.exec = filename.clone();
+    entry.name = if OPTIONS.strip_extension {
+        strip_script_extension(&filename).to_string()
+    } else {
+        filename.clone()
+    };
+    // executables in directories out of `$PATH` can't be found by name
+    let in_path = file
+        .parent()
+        .is_some_and(|dir| ENV_PATHS.iter().any(|p| p == dir));
+    entry.exec = if in_path {
+        filename
+    } else {
+        shlex::quote(&entry.path).into_owned()
+    };
     entry
 }
 
-pub fn load_desktop_entries(history: &EntryMap) -> EntryMap {
+/// Load desktop entries in the application directories. With `prefer_local`, a desktop file
+/// shadows files with the same ID in the following directories.
+pub fn load_desktop_entries(
+    app_dirs: &[PathBuf],
+    history: &EntryMap,
+    prefer_local: bool,
+) -> EntryMap {
     let mut result: EntryMap = IndexMap::new();
-    let app_dirs = get_app_dirs();
+    let mut visited: HashSet<PathBuf> = HashSet::new();
+    // Desktop file IDs found in the preceding application directories. Directories come in
+    // the order of precedence (the user directory first), so a desktop file shadows files with
+    // the same ID in the following directories, even if it is hidden.
+    let mut ids: HashSet<String> = HashSet::new();
     for dir in app_dirs.iter() {
-        let entries = load_desktop_entry_dir(dir, history);
+        let mut dir_ids: HashSet<String> = HashSet::new();
+        let entries = load_desktop_entry_dir(dir, history, &mut visited, &ids, &mut dir_ids);
         result.extend(entries);
+        if prefer_local {
+            ids.extend(dir_ids);
+        }
     }
-    result.sort_by(entry_cmp);
     result
 }
 
-fn load_desktop_entry_dir(dir: &PathBuf, history: &EntryMap) -> EntryMap {
-    let mut entries: EntryMap = IndexMap::new();
-    for path in dir
-        .read_dir()
-        .unwrap()
-        .map(|f| f.expect("Failed to read file").path())
-    {
+/// Collect desktop files under the directory into `files`, in sorted order.
+/// Directories in `visited` are skipped to avoid symlink loops, and nesting deeper than
+/// `MAX_DESKTOP_DIR_DEPTH` is not scanned. Unless `all` is set, autostart directories and
+/// all subdirectories with `--no-recursion` are skipped.
+pub(crate) fn find_desktop_files(
+    dir: &Path,
+    visited: &mut HashSet<PathBuf>,
+    depth: usize,
+    all: bool,
+    files: &mut Vec<PathBuf>,
+) {
+    if depth > MAX_DESKTOP_DIR_DEPTH {
+        return;
+    }
+    match dir.canonicalize() {
+        Ok(real_dir) => {
+            if !visited.insert(real_dir) {
+                return;
+            }
+        }
+        Err(_) => return,
+    }
+    let read_dir = match dir.read_dir() {
+        Ok(read_dir) => read_dir,
+        Err(_) => return,
+    };
+    let mut paths: Vec<PathBuf> = read_dir.filter_map(|f| f.ok()).map(|f| f.path()).collect();
+    paths.sort();
+    for path in paths {
         if path.is_dir() {
-            entries.extend(load_desktop_entry_dir(&path, history));
-        } else {
-            let file = path;
-            match file.extension() {
-                Some(ext) => {
-                    if ext != "desktop" {
-                        continue;
-                    }
-                }
-                None => continue,
+            // entries under autostart directories are started by the session, not by users
+            if !all && (OPTIONS.no_recursion || path.file_name().is_some_and(|n| n == "autostart"))
+            {
+                continue;
             }
-            match load_desktop_entry_file(&file, history) {
-                Some(entry) => {
-                    entries.insert(file.to_str().unwrap().to_string(), entry);
-                }
-                None => continue,
+            find_desktop_files(&path, visited, depth + 1, all, files);
+        } else if path.extension().is_some_and(|ext| ext == "desktop") {
+            files.push(path);
+        }
+    }
+}
+
+// Load desktop entries under the directory, skipping files whose ID is in `shadowed`.
+// IDs of all desktop files found are added to `found`.
+fn load_desktop_entry_dir(
+    dir: &Path,
+    history: &EntryMap,
+    visited: &mut HashSet<PathBuf>,
+    shadowed: &HashSet<String>,
+    found: &mut HashSet<String>,
+) -> EntryMap {
+    let mut entries: EntryMap = IndexMap::new();
+    let mut files = Vec::new();
+    find_desktop_files(dir, visited, 0, false, &mut files);
+    for file in files {
+        let id = desktop_id(&file.to_string_lossy());
+        if shadowed.contains(&id) {
+            continue;
+        }
+        found.insert(id);
+        if let Some(entry) = load_desktop_entry_file(&file, history) {
+            let actions = load_desktop_actions(&file, &entry, history);
+            entries.insert(entry.key(), entry);
+            for action in actions {
+                entries.insert(action.key(), action);
             }
         }
     }
     entries
 }
 
-fn load_desktop_entry_file(file: &PathBuf, history: &EntryMap) -> Option<Entry> {
-    // check file modified time and if it's not modified since prev access, return cached entry
-    let count;
-    let mtime = get_mtime(file);
+// Desktop file ID: path under the applications directory with `/` replaced by `-`
+fn desktop_id(path: &str) -> String {
+    match path.rfind("/applications/") {
+        Some(pos) => path[pos + "/applications/".len()..].replace('/', "-"),
+        None => Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    }
+}
+
+// Override files of a desktop entry in `sklauncher/applications` of the config directories,
+// ordered from lowest to highest priority
+fn get_override_files(id: &str) -> Vec<PathBuf> {
+    match xdg::BaseDirectories::with_prefix("sklauncher") {
+        Ok(base) => base
+            .find_config_files(Path::new("applications").join(id))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// Parse boolean value of a desktop entry key, treating missing or invalid values as false
+fn parse_bool(value: Option<&str>) -> bool {
+    value
+        .and_then(|v| v.parse::<LenientBool>().ok())
+        .is_some_and(|v| v.into())
+}
+
+// Parse a `;`-separated list value of a desktop entry key like `Keywords`
+fn parse_list(value: Option<&str>) -> Vec<String> {
+    value
+        .map(|v| {
+            v.split(';')
+                .filter(|s| !s.is_empty())
+                .map(sanitize)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Merge groups named like `[desktop entry]`, which differ from the spec only in case or
+// surrounding whitespace, into the `Desktop Entry` group unless `--strict-desktop` is given.
+// Keys of the group spelled as the spec take precedence, then those of earlier groups.
+// Returns whether any such group was found.
+fn merge_desktop_entry_groups(conf: &mut ini::Ini) -> bool {
+    if OPTIONS.strict_desktop {
+        return false;
+    }
+    let is_variant = |name: Option<&str>| {
+        name.is_some_and(|name| {
+            name != "Desktop Entry" && name.trim().eq_ignore_ascii_case("Desktop Entry")
+        })
+    };
+    if !conf.sections().any(is_variant) {
+        return false;
+    }
+    let variants: Vec<(String, String)> = conf
+        .iter()
+        .filter(|(name, _)| is_variant(*name))
+        .flat_map(|(_, props)| props.iter())
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    for (key, value) in variants {
+        let exists = conf
+            .section(Some("Desktop Entry"))
+            .is_some_and(|section| section.contains_key(&key));
+        if !exists {
+            conf.with_section(Some("Desktop Entry")).set(key, value);
+        }
+    }
+    true
+}
+
+// Load a desktop entry file with its override files applied. Keys in override files take
+// precedence over the desktop entry file, in the `Desktop Entry` group and action groups.
+// Returns the groups and whether any misspelled `Desktop Entry` group was merged.
+fn load_desktop_conf(file: &Path, overrides: &[PathBuf]) -> Option<(ini::Ini, bool)> {
+    let mut conf = ini::Ini::load_from_file(file).ok()?;
+    let mut merged = merge_desktop_entry_groups(&mut conf);
+    conf.section(Some("Desktop Entry"))?;
+
+    for override_file in overrides.iter() {
+        let mut override_conf = match ini::Ini::load_from_file(override_file) {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+        merged |= merge_desktop_entry_groups(&mut override_conf);
+        for (name, override_section) in override_conf.iter() {
+            let name = match name {
+                Some(name) if name == "Desktop Entry" || name.starts_with("Desktop Action ") => {
+                    name
+                }
+                _ => continue,
+            };
+            let mut overridden: HashSet<&str> = HashSet::new();
+            for (key, value) in override_section.iter() {
+                // the first of duplicated keys is authoritative
+                if overridden.insert(key) {
+                    conf.with_section(Some(name)).set(key, value);
+                }
+            }
+        }
+    }
+    // rust-ini keeps all values of duplicated keys and `get` returns the first one, so the first
+    // occurrence of a key in a group is used
+    Some((conf, merged))
+}
+
+// Check if the desktop entry should not be shown by `Hidden` or `NoDisplay`
+fn is_hidden(section: &ini::Properties) -> bool {
+    parse_bool(section.get("Hidden")) || parse_bool(section.get("NoDisplay"))
+}
+
+// Command of a desktop entry or action group. `X-Sklauncher-Exec` overrides `Exec`, and an empty
+// command is regarded as missing.
+fn desktop_exec(section: &ini::Properties) -> Option<&str> {
+    let non_blank = |exec: &&str| !exec.trim().is_empty();
+    section
+        .get("X-Sklauncher-Exec")
+        .filter(non_blank)
+        .or_else(|| section.get("Exec").filter(non_blank))
+}
+
+fn load_desktop_entry_file(file: &Path, history: &EntryMap) -> Option<Entry> {
+    // check file modified time and if it's not modified since prev access, return cached entry.
+    // override files are taken into account as well.
     let filestr = file.to_str().unwrap().to_string();
-    if history.contains_key(&filestr) {
-        if history.get(&filestr).unwrap().mtime.unwrap() == mtime {
-            return Some(history.get(&filestr).unwrap().clone());
-        } else {
-            count = history.get(&filestr).unwrap().count;
+    let overrides = get_override_files(&desktop_id(&filestr));
+    let mtime = overrides
+        .iter()
+        .map(|o| get_mtime(o))
+        .fold(get_mtime(file), f64::max);
+    let prev = history.get(&filestr);
+    if let Some(prev) = prev {
+        if prev.mtime == Some(mtime) && prev.has_all_keys() {
+            return Some(prev.clone());
         }
-    } else {
-        count = 0;
     }
 
     // desktop entry file is modified or added. load it.
-    let conf = match ini::Ini::load_from_file(file) {
-        Ok(c) => c,
-        Err(_) => return None,
-    };
-    let section = match conf.section(Some("Desktop Entry")) {
-        Some(s) => s,
-        None => return None,
-    };
+    let (conf, merged) = load_desktop_conf(file, &overrides)?;
+    let section = conf.section(Some("Desktop Entry"))?;
+
+    // skip entries which should not be shown
+    if is_hidden(section) {
+        return None;
+    }
+
+    // skip autostart/service entries which are not meant to be launched by hand
+    if AUTOSTART_KEYS.iter().any(|key| section.contains_key(key)) {
+        return None;
+    }
 
     // create new entry from desktop entry
     let mut entry = Entry::new();
     entry.desktop = true;
     entry.path = filestr;
-    entry.count = count;
-    entry.mtime = Some(mtime);
+    if let Some(prev) = prev {
+        entry.restore_history(prev);
+    }
+    // entries of misspelled groups are not cached, so that `--strict-desktop` drops them again
+    entry.mtime = if merged { None } else { Some(mtime) };
     match section.get("Name") {
-        Some(name) => entry.name = name.to_string(),
+        Some(name) => entry.name = sanitize(name),
         _ => return None,
     }
-    match section.get("Exec") {
+    match desktop_exec(section) {
         Some(exec) => entry.exec = exec.to_string(),
-        _ => return None,
+        None if OPTIONS.exec_fallback_tryexec => {
+            // run the program of `TryExec` without arguments if it is installed
+            let try_exec = section
+                .get("TryExec")
+                .filter(|try_exec| !try_exec.trim().is_empty())?
+                .trim();
+            resolve_command(try_exec)?;
+            entry.exec = shlex::quote(try_exec).into_owned();
+            // not cached, so that the entry is dropped again in runs without the option
+            entry.mtime = None;
+        }
+        None => return None,
     }
     match section.get("GenericName") {
-        Some(gname) => entry.generic_name = Some(gname.to_string()),
+        Some(gname) => entry.generic_name = Some(sanitize(gname)),
         None => entry.generic_name = None,
     }
     match section.get("Comment") {
-        Some(comment) => entry.comment = Some(comment.to_string()),
+        Some(comment) => entry.comment = Some(sanitize(comment)),
         None => entry.comment = None,
     }
-    match section.get("Terminal") {
-        Some(terminal) => entry.terminal = terminal.parse::<LenientBool>().unwrap().into(),
-        None => entry.terminal = false,
-    }
+    entry.startup_wm_class = section.get("StartupWMClass").map(sanitize);
+    entry.icon = section.get("Icon").map(sanitize);
+    entry.keywords = parse_list(section.get("Keywords"));
+    entry.categories = parse_list(section.get("Categories"));
+    entry.mime_types = Some(parse_list(section.get("MimeType")));
+    entry.implements = Some(parse_list(section.get("Implements")));
+    entry.terminal = parse_bool(section.get("Terminal"));
+    entry.prefers_non_default_gpu = parse_bool(section.get("PrefersNonDefaultGPU"));
+    entry.actions = section
+        .get("Actions")
+        .map(|actions| {
+            actions
+                .split(';')
+                .filter(|a| !a.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
     Some(entry)
 }
 
+// Load actions of a desktop entry as separate entries named `NAME — ACTION NAME`.
+// Cached actions are used if the desktop entry is not modified.
+fn load_desktop_actions(file: &Path, entry: &Entry, history: &EntryMap) -> Vec<Entry> {
+    let action_key = |id: &String| format!("{}#{}", entry.path, id);
+    let cached: Vec<Entry> = entry
+        .actions
+        .iter()
+        .filter_map(|id| history.get(&action_key(id)))
+        .filter(|action| action.mtime == entry.mtime && action.has_all_keys())
+        .cloned()
+        .collect();
+    if cached.len() == entry.actions.len() {
+        return cached;
+    }
+
+    let overrides = get_override_files(&desktop_id(&entry.path));
+    let conf = match load_desktop_conf(file, &overrides) {
+        Some((conf, _)) => conf,
+        None => return Vec::new(),
+    };
+    entry
+        .actions
+        .iter()
+        .filter_map(|id| {
+            let section = conf.section(Some(format!("Desktop Action {}", id)))?;
+            let mut action = Entry::new();
+            action.desktop = true;
+            action.path = entry.path.clone();
+            action.mtime = entry.mtime;
+            action.action = Some(id.clone());
+            action.name = format!("{} — {}", entry.name, sanitize(section.get("Name")?));
+            action.exec = desktop_exec(section)?.to_string();
+            action.generic_name = entry.generic_name.clone();
+            action.comment = entry.comment.clone();
+            action.startup_wm_class = entry.startup_wm_class.clone();
+            action.keywords = entry.keywords.clone();
+            action.categories = entry.categories.clone();
+            action.mime_types = entry.mime_types.clone();
+            action.implements = entry.implements.clone();
+            action.icon = section
+                .get("Icon")
+                .map(sanitize)
+                .or_else(|| entry.icon.clone());
+            action.terminal = entry.terminal;
+            action.prefers_non_default_gpu = entry.prefers_non_default_gpu;
+            if let Some(prev) = history.get(&action_key(id)) {
+                action.restore_history(prev);
+            }
+            Some(action)
+        })
+        .collect()
+}
+
+// Parse `--entries-file`, a JSON array of entries if the extension is `.json`, otherwise TOML
+fn parse_entries_file(file: &Path) -> Result<Vec<Entry>, String> {
+    let contents = fs::read_to_string(file).map_err(|e| e.to_string())?;
+    if file.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str::<Vec<Entry>>(&contents).map_err(|e| e.to_string())
+    } else {
+        toml::from_str::<EntriesFile>(&contents)
+            .map(|f| f.entry)
+            .map_err(|e| e.to_string())
+    }
+}
+
+// Load entries listed in `--entries-file`. They are launched like desktop entries, and keyed by
+// the file path and the name in the same way as desktop actions.
+fn load_entries_file(file: &Path, history: &EntryMap) -> EntryMap {
+    let listed = match parse_entries_file(file) {
+        Ok(listed) => listed,
+        Err(err) => {
+            eprintln!("Failed to load entries file {}: {}", file.display(), err);
+            std::process::exit(1);
+        }
+    };
+    let mut entries: EntryMap = IndexMap::new();
+    for listed in listed.into_iter() {
+        let mut entry = Entry::new();
+        entry.desktop = true;
+        entry.path = file.to_string_lossy().to_string();
+        entry.name = sanitize(&listed.name);
+        entry.action = Some(entry.name.clone());
+        entry.exec = listed.exec;
+        entry.generic_name = listed.generic_name.as_deref().map(sanitize);
+        entry.comment = listed.comment.as_deref().map(sanitize);
+        entry.startup_wm_class = listed.startup_wm_class;
+        entry.icon = listed.icon;
+        entry.terminal = listed.terminal;
+        if let Some(prev) = history.get(&entry.key()) {
+            entry.restore_history(prev);
+        }
+        entries.insert(entry.key(), entry);
+    }
+    entries
+}
+
+// Patterns of `--exclude` followed by those listed in `sklauncher/exclude` of the config
+// directories, skipping blank lines and `#` comments
+fn get_exclude_patterns() -> Vec<Regex> {
+    let mut patterns = OPTIONS.exclude.clone();
+    let files = match xdg::BaseDirectories::with_prefix("sklauncher") {
+        Ok(base) => base.find_config_files("exclude").collect(),
+        Err(_) => Vec::new(),
+    };
+    for file in files {
+        let content = match fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_glob(line) {
+                Ok(pattern) => patterns.push(pattern),
+                Err(err) => eprintln!("Invalid pattern in {}: {}: {}", file.display(), line, err),
+            }
+        }
+    }
+    patterns
+}
+
+/// Check if the entry is hidden by `--exclude`, matching its name or desktop ID
+pub fn is_excluded(entry: &Entry) -> bool {
+    matches_patterns(entry, &EXCLUDE)
+}
+
+// Check if any of the patterns matches the name or the desktop ID of the entry
+fn matches_patterns(entry: &Entry, patterns: &[Regex]) -> bool {
+    let id = if entry.desktop {
+        desktop_id(&entry.path)
+    } else {
+        String::new()
+    };
+    patterns
+        .iter()
+        .any(|re| re.is_match(&entry.name) || (!id.is_empty() && re.is_match(&id)))
+}
+
 pub fn load_entries() -> EntryMap {
     let history: EntryMap = load_history();
-    let mut entries: EntryMap = load_desktop_entries(&history);
-    entries.extend(load_bin_entries(&history));
-    save_history(&entries);
+    let mut entries: EntryMap = IndexMap::new();
+    if !OPTIONS.entries_only {
+        entries.extend(load_desktop_entries(
+            &get_app_dirs(),
+            &history,
+            !OPTIONS.no_prefer_local,
+        ));
+        entries.extend(load_bin_entries(&BIN_PATHS, &history));
+    }
+    if let Some(file) = &OPTIONS.entries_file {
+        entries.extend(load_entries_file(file, &history));
+    }
+    if OPTIONS.entries_only {
+        // keep entries of other runs in the history, they are not shown by `--entries-only`
+        for (key, entry) in history.into_iter() {
+            entries.entry(key).or_insert(entry);
+        }
+    }
+    save_history(&mut entries);
 
     entries
 }
+
+/// Explain on stderr why no entries could be loaded
+pub fn report_empty_sources() {
+    eprintln!("No entries were loaded.");
+    if BIN_PATHS.is_empty() {
+        eprintln!("  - $PATH is empty or contains no existing directories");
+    } else {
+        eprintln!("  - no executable files were found in $PATH");
+    }
+    if get_app_dirs().is_empty() {
+        eprintln!("  - no application directories exist in $XDG_DATA_HOME or $XDG_DATA_DIRS");
+    } else {
+        eprintln!("  - no valid desktop entries were found in the application directories");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Empty directory for a test under the system temporary directory
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("sklauncher-{}-{}", std::process::id(), name));
+        drop(fs::remove_dir_all(&dir));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Displayed text of the entry and the chars highlighted for the matches
+    fn render(entry: &Entry, options: &Cli, matches: Matches) -> (String, String) {
+        let highlight = Attr {
+            effect: Effect::BOLD,
+            ..Attr::default()
+        };
+        let text = entry.text();
+        let context = DisplayContext {
+            text: &text,
+            score: 0,
+            matches,
+            container_width: 80,
+            highlight_attr: highlight,
+        };
+        let display = entry.render(context, options);
+        let highlighted = display
+            .iter()
+            .filter(|(_, attr)| *attr == highlight)
+            .map(|(c, _)| c)
+            .collect();
+        (display.stripped().to_string(), highlighted)
+    }
+
+    fn write_executable(file: &Path) {
+        fs::write(file, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(file, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    // Application directories of the user and the system, each with a desktop file of the same
+    // ID but a different command
+    fn user_and_system_app_dirs(name: &str) -> (PathBuf, [PathBuf; 2]) {
+        let dir = temp_dir(name);
+        let app_dirs = [
+            dir.join("user/applications"),
+            dir.join("system/applications"),
+        ];
+        for (app_dir, exec) in app_dirs.iter().zip(["user-app", "system-app"]) {
+            fs::create_dir_all(app_dir).unwrap();
+            let content = format!(
+                "[Desktop Entry]\nType=Application\nName=App\nExec={}\n",
+                exec
+            );
+            fs::write(app_dir.join("sklauncher-test-app.desktop"), content).unwrap();
+        }
+        (dir, app_dirs)
+    }
+
+    #[test]
+    fn prefer_local_keeps_only_user_entry() {
+        let (dir, app_dirs) = user_and_system_app_dirs("prefer-local");
+        let entries = load_desktop_entries(&app_dirs, &IndexMap::new(), true);
+        let execs: Vec<&str> = entries.values().map(|e| e.exec.as_str()).collect();
+        assert_eq!(execs, ["user-app"]);
+
+        // both are listed with `--no-prefer-local`
+        let entries = load_desktop_entries(&app_dirs, &IndexMap::new(), false);
+        let execs: Vec<&str> = entries.values().map(|e| e.exec.as_str()).collect();
+        assert_eq!(execs, ["user-app", "system-app"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn scripts_in_bin_dir_are_bin_entries() {
+        let dir = temp_dir("bin-dir");
+        write_executable(&dir.join("hello.sh"));
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        let options = Cli::parse_from(["sklauncher", "--bin-dir", dir.to_str().unwrap()]);
+        let bin_dirs = get_paths(&options);
+        assert_eq!(bin_dirs.last(), Some(&dir));
+
+        let entries = load_bin_entries(&bin_dirs[bin_dirs.len() - 1..], &IndexMap::new());
+        let script = dir.join("hello.sh");
+        let entry = &entries[script.to_str().unwrap()];
+        assert!(!entry.desktop);
+        assert_eq!(entry.name, "hello.sh");
+        // not in `$PATH`, so launched by the absolute path
+        assert_eq!(entry.exec, shlex::quote(script.to_str().unwrap()));
+        // files without the executable bit are skipped
+        assert_eq!(entries.len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn escape_sequences_stripped_from_desktop_entry() {
+        let dir = temp_dir("sanitize");
+        let file = dir.join("sklauncher-test-evil.desktop");
+        fs::write(
+            &file,
+            "[Desktop Entry]\nType=Application\nName=\x1b[31mEvil\x1b[m\x07App\n\
+             Comment=Say \x1b]0;title\x07hi\nExec=evil\n",
+        )
+        .unwrap();
+        let entry = load_desktop_entry_file(&file, &IndexMap::new()).unwrap();
+        assert_eq!(entry.name, "EvilApp");
+        assert_eq!(entry.comment.as_deref(), Some("Say 0;titlehi"));
+
+        // matches of the sanitized name are highlighted at the same chars
+        let (text, highlighted) = render(&entry, &OPTIONS, Matches::CharRange(0, 4));
+        assert_eq!(text, "\u{f108}  EvilApp");
+        assert_eq!(highlighted, "Evil");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn app(name: &str, generic_name: Option<&str>, comment: Option<&str>) -> Entry {
+        let mut entry = Entry::new();
+        entry.desktop = true;
+        entry.path = format!("/usr/share/applications/{}.desktop", name.to_lowercase());
+        entry.name = name.to_string();
+        entry.generic_name = generic_name.map(str::to_string);
+        entry.comment = comment.map(str::to_string);
+        entry
+    }
+
+    #[test]
+    fn generic_name_shown_after_separator() {
+        let options = Cli::parse_from([
+            "sklauncher",
+            "--show-generic-name",
+            "--generic-separator",
+            " \u{2014} ",
+        ]);
+        let entry = app("Café", Some("Web Browser"), None);
+        let (text, highlighted) = render(&entry, &options, Matches::CharRange(2, 4));
+        assert_eq!(text, "\u{f108}  Café \u{2014} Web Browser");
+        assert_eq!(highlighted, "fé");
+
+        // without a generic name, no separator is left
+        let entry = app("Café", None, None);
+        let (text, _) = render(&entry, &options, Matches::None);
+        assert_eq!(text, "\u{f108}  Café");
+    }
+
+    fn bin(name: &str) -> Entry {
+        let mut entry = Entry::new();
+        entry.path = format!("/usr/bin/{}", name);
+        entry.name = name.to_string();
+        entry
+    }
+
+    // Names of entries after sorting them for the finder
+    fn sorted_names(entries: Vec<Entry>, options: &Cli) -> Vec<String> {
+        let mut entries: EntryMap = entries.into_iter().map(|e| (e.key(), e)).collect();
+        sort_entries(&mut entries, options);
+        entries.into_values().map(|e| e.name).collect()
+    }
+
+    #[test]
+    fn entries_ordered_by_count_then_kind_then_name() {
+        let with_count = |mut entry: Entry, count: u32| {
+            entry.count = count;
+            entry
+        };
+        let entries = vec![
+            with_count(bin("vim"), 2),
+            with_count(app("Files", None, None), 0),
+            with_count(bin("htop"), 5),
+            with_count(bin("bash"), 0),
+            with_count(app("Editor", None, None), 2),
+            with_count(app("Browser", None, None), 0),
+        ];
+        assert_eq!(
+            sorted_names(entries, &Cli::parse_from(["sklauncher"])),
+            ["htop", "Editor", "vim", "Browser", "Files", "bash"]
+        );
+    }
+
+    #[test]
+    fn dangling_symlinks_in_bin_dir_skipped() {
+        let dir = temp_dir("dangling");
+        write_executable(&dir.join("tool"));
+        std::os::unix::fs::symlink(dir.join("tool"), dir.join("linked")).unwrap();
+        std::os::unix::fs::symlink(dir.join("removed"), dir.join("dangling")).unwrap();
+        let entries = load_bin_entries(std::slice::from_ref(&dir), &IndexMap::new());
+        let mut names: Vec<&str> = entries.values().map(|e| e.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["linked", "tool"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn label_format_substitutes_placeholders() {
+        let template = "{name} \u{2014} {generic} ({comment})";
+        assert_eq!(
+            render_label(template, "Firefox", "Web Browser", "Browse the Web"),
+            (
+                "Firefox \u{2014} Web Browser (Browse the Web)".to_string(),
+                Some(0)
+            )
+        );
+        assert_eq!(
+            render_label("{comment}", "Firefox", "", "Browse"),
+            ("Browse".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn label_format_drops_separators_of_missing_fields() {
+        let template = "{name} \u{2014} {generic} ({comment})";
+        assert_eq!(
+            render_label(template, "Firefox", "", "Browse"),
+            ("Firefox (Browse)".to_string(), Some(0))
+        );
+        assert_eq!(
+            render_label(template, "Firefox", "Web Browser", ""),
+            ("Firefox \u{2014} Web Browser".to_string(), Some(0))
+        );
+        assert_eq!(
+            render_label(template, "Firefox", "", ""),
+            ("Firefox".to_string(), Some(0))
+        );
+    }
+
+    #[test]
+    fn label_format_with_generic_name_first() {
+        assert_eq!(
+            render_label("{generic}: {name}", "Firefox", "Web Browser", ""),
+            ("Web Browser: Firefox".to_string(), Some(13))
+        );
+        // the name moves to the start when the generic name is missing
+        assert_eq!(
+            render_label("{generic}: {name}", "Firefox", "", ""),
+            ("Firefox".to_string(), Some(0))
+        );
+    }
+
+    #[test]
+    fn label_format_highlights_name_at_its_position() {
+        let options = Cli::parse_from(["sklauncher", "--label-format", "{generic}: {name}"]);
+        let entry = app("Firefox", Some("Web Browser"), None);
+        assert_eq!(
+            entry.label(&options),
+            ("Web Browser: Firefox".to_string(), Some(13), 7)
+        );
+        let (text, highlighted) = render(&entry, &options, Matches::CharRange(0, 3));
+        assert_eq!(text, "\u{f108}  Web Browser: Firefox");
+        assert_eq!(highlighted, "Fir");
+        // matches past the name are not highlighted in other fields
+        let (_, highlighted) = render(&entry, &options, Matches::CharIndices(&[6, 7, 9]));
+        assert_eq!(highlighted, "x");
+    }
+
+    #[test]
+    fn exec_overridden_by_sklauncher_exec() {
+        let conf = ini::Ini::load_from_str(
+            "[a]\nExec=orig\nX-Sklauncher-Exec=custom\n[b]\nExec=orig\nX-Sklauncher-Exec= \n",
+        )
+        .unwrap();
+        let exec = |name: &str| desktop_exec(conf.section(Some(name)).unwrap());
+        assert_eq!(exec("a"), Some("custom"));
+        // a blank override is ignored
+        assert_eq!(exec("b"), Some("orig"));
+    }
+
+    #[test]
+    fn entry_launches_sklauncher_exec() {
+        let dir = temp_dir("sklauncher-exec");
+        fs::write(
+            dir.join("app.desktop"),
+            "[Desktop Entry]\nType=Application\nName=App\nExec=app\nX-Sklauncher-Exec=app --safe\n",
+        )
+        .unwrap();
+        let entries = load_desktop_entries(std::slice::from_ref(&dir), &IndexMap::new(), true);
+        let execs: Vec<&str> = entries.values().map(|e| e.exec.as_str()).collect();
+        assert_eq!(execs, ["app --safe"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn id_shown_after_name_without_matching_it() {
+        let options = Cli::parse_from(["sklauncher", "--show-id"]);
+        let entry = app("Firefox", None, None);
+        // the ID is not part of the match text
+        assert_eq!(entry.text(), "Firefox");
+        let (text, highlighted) = render(&entry, &options, Matches::CharRange(0, 3));
+        assert_eq!(text, "\u{f108}  Firefox  firefox.desktop");
+        assert_eq!(highlighted, "Fir");
+        // highlights of the name don't spill into the ID
+        let (_, highlighted) = render(&entry, &options, Matches::CharRange(4, 12));
+        assert_eq!(highlighted, "fox");
+
+        let (text, _) = render(&bin("htop"), &options, Matches::None);
+        assert_eq!(text, "\u{f120}  htop  /usr/bin/htop");
+    }
+
+    #[test]
+    fn desktop_id_of_nested_file() {
+        assert_eq!(
+            desktop_id("/usr/share/applications/kde4/konsole.desktop"),
+            "kde4-konsole.desktop"
+        );
+        assert_eq!(desktop_id("/opt/foo.desktop"), "foo.desktop");
+    }
+
+    #[test]
+    fn parse_lenient_bool() {
+        assert!(parse_bool(Some("true")));
+        assert!(parse_bool(Some("True")));
+        assert!(parse_bool(Some("1")));
+        assert!(!parse_bool(Some("false")));
+        assert!(!parse_bool(Some("maybe")));
+        assert!(!parse_bool(None));
+    }
+
+    #[test]
+    fn override_file_hides_system_entry() {
+        let dir = temp_dir("override-hidden");
+        let file = dir.join("app.desktop");
+        fs::write(&file, "[Desktop Entry]\nName=App\nExec=app\n").unwrap();
+        let override_file = dir.join("override.desktop");
+        fs::write(&override_file, "[Desktop Entry]\nNoDisplay=true\n").unwrap();
+        let (conf, _) = load_desktop_conf(&file, &[]).unwrap();
+        assert!(!is_hidden(conf.section(Some("Desktop Entry")).unwrap()));
+        let (conf, _) = load_desktop_conf(&file, &[override_file]).unwrap();
+        let section = conf.section(Some("Desktop Entry")).unwrap();
+        assert!(is_hidden(section));
+        assert_eq!(section.get("Exec"), Some("app"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn override_files_apply_to_entry_and_actions() {
+        let dir = temp_dir("override");
+        let file = dir.join("app.desktop");
+        fs::write(
+            &file,
+            "[Desktop Entry]\nName=App\nExec=app\nActions=new;\n\
+             [Desktop Action new]\nName=New\nExec=app --new\n",
+        )
+        .unwrap();
+        let override_file = dir.join("override.desktop");
+        fs::write(
+            &override_file,
+            "[Desktop Entry]\nExec=first\nExec=second\n\
+             [Desktop Action new]\nExec=app --new-window\n[Other]\nExec=other\n",
+        )
+        .unwrap();
+        let (conf, merged) = load_desktop_conf(&file, &[override_file]).unwrap();
+        assert!(!merged);
+        let entry = conf.section(Some("Desktop Entry")).unwrap();
+        assert_eq!(entry.get("Name"), Some("App"));
+        // the first of duplicated keys in an override file is used
+        assert_eq!(entry.get("Exec"), Some("first"));
+        let action = conf.section(Some("Desktop Action new")).unwrap();
+        assert_eq!(action.get("Exec"), Some("app --new-window"));
+        assert!(conf.section(Some("Other")).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn query_of_app_and_action_names_matches_action() {
+        let dir = temp_dir("actions");
+        fs::write(
+            dir.join("firefox.desktop"),
+            "[Desktop Entry]\nType=Application\nName=Firefox\nExec=firefox\n\
+             Actions=new-window;private;\n\
+             [Desktop Action new-window]\nName=New Window\nExec=firefox --new-window\n\
+             [Desktop Action private]\nName=New Private Window\nExec=firefox --private-window\n",
+        )
+        .unwrap();
+        let entries = load_desktop_entries(std::slice::from_ref(&dir), &IndexMap::new(), true);
+        let texts: Vec<String> = entries.values().map(|e| e.text().into_owned()).collect();
+        assert_eq!(
+            texts,
+            [
+                "Firefox",
+                "Firefox \u{2014} New Window",
+                "Firefox \u{2014} New Private Window"
+            ]
+        );
+
+        let factory = AndOrEngineFactory::new(ExactOrFuzzyEngineFactory::builder().build());
+        let engine = factory.create_engine_with_case("firefox private", CaseMatching::Smart);
+        let matched: Vec<&str> = entries
+            .values()
+            .filter(|e| {
+                let item: Arc<dyn SkimItem> = Arc::new((*e).clone());
+                engine.match_item(item).is_some()
+            })
+            .map(|e| e.exec.as_str())
+            .collect();
+        assert_eq!(matched, ["firefox --private-window"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn find_desktop_files_in_nested_directories() {
+        let dir = temp_dir("walk");
+        fs::create_dir_all(dir.join("kde/deeper")).unwrap();
+        fs::create_dir_all(dir.join("autostart")).unwrap();
+        fs::write(dir.join("b.desktop"), "").unwrap();
+        fs::write(dir.join("a.desktop"), "").unwrap();
+        fs::write(dir.join("readme.txt"), "").unwrap();
+        fs::write(dir.join("kde/deeper/c.desktop"), "").unwrap();
+        fs::write(dir.join("autostart/d.desktop"), "").unwrap();
+        // a symlink loop is scanned only once
+        std::os::unix::fs::symlink(&dir, dir.join("kde/loop")).unwrap();
+
+        let mut files = Vec::new();
+        find_desktop_files(&dir, &mut HashSet::new(), 0, false, &mut files);
+        let names = ["a.desktop", "b.desktop", "kde/deeper/c.desktop"];
+        assert_eq!(files, names.map(|name| dir.join(name)));
+
+        let mut files = Vec::new();
+        find_desktop_files(&dir, &mut HashSet::new(), 0, true, &mut files);
+        assert_eq!(files.len(), 4);
+        assert!(files.contains(&dir.join("autostart/d.desktop")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn count_shown_after_name_without_matching_it() {
+        let options = Cli::parse_from(["sklauncher", "--show-count"]);
+        let mut entry = app("Firefox", None, None);
+        entry.count = 42;
+        assert_eq!(entry.text(), "Firefox");
+        let (text, highlighted) = render(&entry, &options, Matches::CharIndices(&[0, 4, 6]));
+        assert_eq!(text, "\u{f108}  Firefox  \u{d7}42");
+        assert_eq!(highlighted, "Ffx");
+        // entries never launched have no count
+        entry.count = 0;
+        let (text, _) = render(&entry, &options, Matches::None);
+        assert_eq!(text, "\u{f108}  Firefox");
+    }
+
+    #[test]
+    fn blank_exec_is_missing() {
+        let conf = ini::Ini::load_from_str("[a]\nExec=  \n[b]\nExec=\n[c]\nName=c\n").unwrap();
+        let exec = |name: &str| desktop_exec(conf.section(Some(name)).unwrap());
+        assert_eq!(exec("a"), None);
+        assert_eq!(exec("b"), None);
+        assert_eq!(exec("c"), None);
+    }
+
+    #[test]
+    fn entry_with_empty_exec_dropped() {
+        let dir = temp_dir("empty-exec");
+        fs::write(
+            dir.join("empty.desktop"),
+            "[Desktop Entry]\nType=Application\nName=Empty\nExec=\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("full.desktop"),
+            "[Desktop Entry]\nType=Application\nName=Full\nExec=full\n",
+        )
+        .unwrap();
+        let entries = load_desktop_entries(std::slice::from_ref(&dir), &IndexMap::new(), true);
+        let names: Vec<&str> = entries.values().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["Full"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parse_list_drops_empty_items() {
+        assert_eq!(
+            parse_list(Some("Utility;;Text\x1b[31mEditor;")),
+            ["Utility", "TextEditor"]
+        );
+        assert!(parse_list(None).is_empty());
+    }
+
+    #[test]
+    fn match_all_finds_app_by_other_fields() {
+        let mut entry = app("Pinta", Some("Image Editor"), Some("Edit raster images"));
+        entry.keywords = parse_list(Some("paint;drawing;"));
+        entry.categories = parse_list(Some("Graphics;2DGraphics;"));
+        let factory = AndOrEngineFactory::new(ExactOrFuzzyEngineFactory::builder().build());
+        let matches = |options: &Cli, query: &str| {
+            let engine = factory.create_engine_with_case(query, CaseMatching::Smart);
+            let item: Arc<dyn SkimItem> = Arc::new(entry.match_text(options).into_owned());
+            engine.match_item(item).is_some()
+        };
+        let options = Cli::parse_from(["sklauncher", "--match-all"]);
+        assert_eq!(
+            entry.match_text(&options),
+            "Pinta, Image Editor, Edit raster images, paint, drawing, Graphics, 2DGraphics"
+        );
+        let default = Cli::parse_from(["sklauncher"]);
+        for query in ["'graphics", "'paint", "'raster"] {
+            assert!(matches(&options, query), "{:?}", query);
+            assert!(!matches(&default, query), "{:?}", query);
+        }
+    }
+
+    #[test]
+    fn equal_entries_ordered_by_name_unless_disabled() {
+        let entries = || vec![bin("zsh"), bin("awk"), bin("make")];
+        assert_eq!(
+            sorted_names(entries(), &Cli::parse_from(["sklauncher"])),
+            ["awk", "make", "zsh"]
+        );
+        // the load order is kept
+        let options = Cli::parse_from(["sklauncher", "--no-name-tiebreak"]);
+        assert_eq!(sorted_names(entries(), &options), ["zsh", "awk", "make"]);
+    }
+
+    #[test]
+    fn user_desktop_file_overrides_exec_of_system_app() {
+        let (dir, app_dirs) = user_and_system_app_dirs("user-override");
+        // the same ID is given by a nested file in the system directory
+        fs::create_dir_all(app_dirs[1].join("sklauncher")).unwrap();
+        fs::write(
+            app_dirs[1].join("sklauncher/test.desktop"),
+            "[Desktop Entry]\nType=Application\nName=Nested\nExec=system-nested\n",
+        )
+        .unwrap();
+        fs::write(
+            app_dirs[0].join("sklauncher-test.desktop"),
+            "[Desktop Entry]\nType=Application\nName=Nested\nExec=user-nested\n",
+        )
+        .unwrap();
+        let entries = load_desktop_entries(&app_dirs, &IndexMap::new(), true);
+        let user_file = app_dirs[0].join("sklauncher-test-app.desktop");
+        let apps: Vec<&Entry> = entries.values().filter(|e| e.name == "App").collect();
+        assert_eq!(apps.len(), 1);
+        assert_eq!(apps[0].path, user_file.to_str().unwrap());
+        assert_eq!(apps[0].exec, "user-app");
+        let execs: Vec<&str> = entries
+            .values()
+            .filter(|e| e.name == "Nested")
+            .map(|e| e.exec.as_str())
+            .collect();
+        assert_eq!(execs, ["user-nested"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn history_score_blends_count_and_recency() {
+        let used = |name: &str, count: u32, last_used: Option<u64>| {
+            let mut entry = bin(name);
+            entry.count = count;
+            entry.last_used = last_used;
+            entry
+        };
+        // `often` was launched the most but long ago, `recent` was launched last
+        let entries = [
+            used("often", 10, Some(1_000)),
+            used("recent", 2, Some(2_000)),
+            used("never", 0, None),
+        ];
+        let scale = HistoryScale::new(entries.iter());
+        let scores =
+            |weight: f64| -> Vec<f64> { entries.iter().map(|e| scale.score(e, weight)).collect() };
+        // pure launch count
+        assert_eq!(scores(0.0), [1.0, 0.2, 0.0]);
+        // pure recency, where the earliest use still scores above never
+        assert_eq!(scores(1.0), [1.0 / 1001.0, 1.0, 0.0]);
+        let blend = scores(0.5);
+        assert_eq!(blend[0], 0.5 + 0.5 / 1001.0);
+        assert_eq!(blend[1], 0.1 + 0.5);
+        assert_eq!(blend[2], 0.0);
+
+        let options = Cli::parse_from(["sklauncher", "--history-weight", "0.9"]);
+        assert_eq!(
+            sorted_names(entries.to_vec(), &options),
+            ["recent", "often", "never"]
+        );
+        let options = Cli::parse_from(["sklauncher", "--history-weight", "0.1"]);
+        assert_eq!(
+            sorted_names(entries.to_vec(), &options),
+            ["often", "recent", "never"]
+        );
+    }
+
+    #[test]
+    fn exclude_matches_name_or_desktop_id() {
+        let patterns = vec![
+            parse_glob("org.gnome.*").unwrap(),
+            parse_glob("Qt V4L2*").unwrap(),
+        ];
+        let mut maps = app("Maps", None, None);
+        maps.path = "/usr/share/applications/org.gnome.Maps.desktop".to_string();
+        assert!(matches_patterns(&maps, &patterns));
+        assert!(matches_patterns(
+            &app("Qt V4L2 test utility", None, None),
+            &patterns
+        ));
+        assert!(!matches_patterns(&app("Firefox", None, None), &patterns));
+    }
+
+    #[test]
+    fn exclude_matches_only_name_of_executables() {
+        let patterns = vec![parse_glob("*.desktop").unwrap()];
+        assert!(!matches_patterns(&bin("tool"), &patterns));
+        assert!(matches_patterns(&bin("tool.desktop"), &patterns));
+    }
+
+    #[test]
+    fn merge_misspelled_desktop_entry_group() {
+        let mut conf = ini::Ini::load_from_str(
+            "[Desktop Entry]\nName=Spec\n[desktop entry]\nName=Lower\nExec=lower\n\
+             [ DESKTOP ENTRY ]\nExec=upper\nIcon=upper\n",
+        )
+        .unwrap();
+        assert!(merge_desktop_entry_groups(&mut conf));
+        let section = conf.section(Some("Desktop Entry")).unwrap();
+        assert_eq!(section.get("Name"), Some("Spec"));
+        assert_eq!(section.get("Exec"), Some("lower"));
+        assert_eq!(section.get("Icon"), Some("upper"));
+
+        let mut conf = ini::Ini::load_from_str("[Desktop Entry]\nName=Spec\n").unwrap();
+        assert!(!merge_desktop_entry_groups(&mut conf));
+    }
+
+    #[test]
+    fn desktop_file_without_entry_group_is_rejected() {
+        let dir = temp_dir("no-group");
+        let file = dir.join("app.desktop");
+        fs::write(&file, "[Desktop Action new]\nName=New\nExec=app\n").unwrap();
+        assert!(load_desktop_conf(&file, &[]).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bin_locations_in_order_of_lookup() {
+        let dir = temp_dir("bin-order");
+        let (first, second) = (dir.join("first"), dir.join("second"));
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+        write_executable(&first.join("tool"));
+        write_executable(&second.join("tool"));
+        let path = second.join("tool");
+        let locations: Vec<PathBuf> = bin_locations(path.to_str().unwrap(), &[first, second])
+            .into_iter()
+            .map(|(location, _)| location)
+            .collect();
+        assert_eq!(locations, [dir.join("first/tool"), dir.join("second/tool")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bin_locations_skip_same_file_by_linked_directory() {
+        let dir = temp_dir("bin-link");
+        let usr_bin = dir.join("usr-bin");
+        fs::create_dir_all(&usr_bin).unwrap();
+        std::os::unix::fs::symlink(&usr_bin, dir.join("bin")).unwrap();
+        write_executable(&usr_bin.join("ls"));
+        fs::write(usr_bin.join("data"), "").unwrap();
+        let bin_dirs = [dir.join("bin"), usr_bin.clone()];
+        let locations = bin_locations(dir.join("bin/ls").to_str().unwrap(), &bin_dirs);
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].0, dir.join("bin/ls"));
+        // files which are not executable are not listed
+        let data = usr_bin.join("data");
+        assert!(bin_locations(data.to_str().unwrap(), &bin_dirs).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn names_sorted_ignoring_case_unless_case_sensitive() {
+        let entries = || vec![bin("vim"), bin("Xorg"), bin("awk"), bin("Vim"), bin("Awk")];
+        assert_eq!(
+            sorted_names(entries(), &Cli::parse_from(["sklauncher"])),
+            ["Awk", "awk", "Vim", "vim", "Xorg"]
+        );
+        let options = Cli::parse_from(["sklauncher", "--case-sensitive-sort"]);
+        assert_eq!(
+            sorted_names(entries(), &options),
+            ["Awk", "Vim", "Xorg", "awk", "vim"]
+        );
+    }
+
+    #[test]
+    fn flatten_renders_without_icon_shift() {
+        let options = Cli::parse_from(["sklauncher", "--flatten"]);
+        let (text, highlighted) =
+            render(&app("Café", None, None), &options, Matches::CharRange(2, 4));
+        assert_eq!(text, "Café");
+        assert_eq!(highlighted, "fé");
+        let (text, highlighted) = render(&bin("htop"), &options, Matches::CharIndices(&[0, 3]));
+        assert_eq!(text, "htop");
+        assert_eq!(highlighted, "hp");
+    }
+}
@@ -1,76 +1,1058 @@
+use std::borrow::Cow;
 use std::env;
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use indexmap::IndexMap;
 use regex::Regex;
 
-use crate::entry::{Entry, OPTIONS};
+use crate::clipboard::CLIPBOARD;
+use crate::entry::{is_executable_file, now, Entry, OPTIONS};
 use crate::history::save_history;
+use crate::options::{Cli, GtkLaunch, PrimeVendor, TerminalHold};
+
+// Period after spawning in which an exiting command is regarded as a failed launch
+const FAIL_FAST_PERIOD: Duration = Duration::from_millis(200);
+
+// Programs which run their arguments as another command. `env` is looked through when it has
+// no options but `-i`, and commands of the others are not checked by `--deny` and `--allow`.
+const WRAPPERS: [&str; 25] = [
+    "env",
+    "sudo",
+    "doas",
+    "pkexec",
+    "su",
+    "nohup",
+    "nice",
+    "ionice",
+    "chrt",
+    "taskset",
+    "setsid",
+    "stdbuf",
+    "timeout",
+    "time",
+    "exec",
+    "command",
+    "xargs",
+    "systemd-run",
+    "flatpak-spawn",
+    "sh",
+    "bash",
+    "dash",
+    "zsh",
+    "ksh",
+    "fish",
+];
 
 lazy_static! {
-    static ref RE_EXEC_OPT: Regex = Regex::new(r"\s*%\w").unwrap();
+    static ref RE_FIELD_CODE: Regex = Regex::new(r"(\s*)%([%\w])").unwrap();
+    static ref RE_ASSIGNMENT: Regex = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*=").unwrap();
+    static ref USE_SETSID: bool = !OPTIONS.no_setsid && !OPTIONS.wait && setsid_available();
+    static ref SESSION_ENV: Vec<(OsString, OsString)> = capture_session_env();
+    static ref USE_GTK_LAUNCH: bool = gtk_launch_enabled();
+    static ref OPEN_ARGS: Vec<String> = get_open_args();
+    // wait for commands failing right after spawning only if the result is used, so that
+    // normal launches return at once
+    static ref FAIL_FAST: bool = OPTIONS.strict || OPTIONS.launch_count_increment_only_on_success;
+}
+
+/// Resolve command name to an executable file like the shell does
+pub fn resolve_command(name: &str) -> Option<PathBuf> {
+    if name.contains('/') {
+        let path = Path::new(name);
+        return is_executable_file(path).then(|| path.to_path_buf());
+    }
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|path| is_executable_file(path))
+}
+
+// Check if `setsid` command exists in $PATH, warning if it doesn't
+fn setsid_available() -> bool {
+    let found = resolve_command("setsid").is_some();
+    if !found {
+        eprintln!("setsid is not found in $PATH, launching without it");
+    }
+    found
+}
+
+// Capture variables of the login environment of the user's shell for `--session-env`,
+// warning and returning nothing if the shell fails
+fn capture_session_env() -> Vec<(OsString, OsString)> {
+    let shell = env::var_os("SHELL").unwrap_or_else(|| "sh".into());
+    let output = Command::new(&shell)
+        .args(["-lc", "exec env -0"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+    match output {
+        Ok(output) if output.status.success() => output
+            .stdout
+            .split(|&b| b == 0)
+            .filter_map(|var| {
+                let pos = var.iter().position(|&b| b == b'=')?;
+                let name = OsString::from_vec(var[..pos].to_vec());
+                let value = OsString::from_vec(var[pos + 1..].to_vec());
+                Some((name, value))
+            })
+            .collect(),
+        _ => {
+            eprintln!(
+                "Failed to capture login environment of {}, using the current environment",
+                shell.to_string_lossy()
+            );
+            Vec::new()
+        }
+    }
 }
 
 pub fn execute_raw(cmd: String) {
-    _exec(cmd.trim());
+    if let Err(err) = check_raw_allowed(&cmd, &OPTIONS.allow, &OPTIONS.deny) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    let cmd = apply_prefix(cmd.trim().to_string(), &OPTIONS);
+    if OPTIONS.dry_run {
+        eprintln!("source: raw query");
+        dry_run(&cmd, None);
+        return;
+    }
+    let vars = launch_env(None);
+    if OPTIONS.wait {
+        exit_with(exec_wait(&cmd, &vars));
+    }
+    if let Err(err) = _exec(&cmd, &vars, *FAIL_FAST) {
+        eprintln!("Failed to launch command: {}", err);
+    }
 }
 
 pub fn execute(pathstr: String, entries: &mut IndexMap<String, Entry>) {
+    let entry = entries.get(&pathstr).unwrap().clone();
+
+    if let Err(err) = entry_words(&entry)
+        .and_then(|words| check_command_allowed(&words, &OPTIONS.allow, &OPTIONS.deny))
+    {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+
+    if OPTIONS.dry_run {
+        trace_entry(&entry);
+        let cmd = gtk_launch_command(&entry).unwrap_or_else(|| build_command(&entry));
+        dry_run(&cmd, Some(&entry));
+        return;
+    }
+
+    if OPTIONS.require_exec_resolves {
+        if let Err(err) = check_exec_resolves(&entry) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+
+    let vars = launch_env(Some(&entry));
+    let result = if OPTIONS.wait {
+        exec_wait(&build_command(&entry), &vars)
+    } else {
+        // gtk-launch exits right after starting the application, so it's always waited for to
+        // fall back to Exec on failure
+        gtk_launch_command(&entry)
+            .and_then(|cmd| match _exec(&cmd, &vars, true) {
+                Ok(()) => Some(Ok(0)),
+                Err(err) => {
+                    eprintln!("Failed to launch with gtk-launch, using Exec: {}", err);
+                    None
+                }
+            })
+            .unwrap_or_else(|| _exec(&build_command(&entry), &vars, *FAIL_FAST).map(|()| 0))
+    };
+
+    // record the launch and its failure, if any, to show it in the next preview
     let entry = entries.get_mut(&pathstr).unwrap();
-    entry.count += 1;
-    let entry = entry.clone();
+    let succeeded = result == Ok(0);
+    if succeeded || !OPTIONS.launch_count_increment_only_on_success {
+        entry.count = entry.count.saturating_add(OPTIONS.history_boost);
+        entry.last_used = Some(now());
+    }
+    // an unchecked launch tells nothing about the previous failure, so keep it
+    if OPTIONS.wait || *FAIL_FAST || result.is_err() {
+        entry.last_error = launch_error(&result);
+    }
     save_history(entries);
 
-    if !entry.desktop {
-        exec_command(entry);
+    if OPTIONS.wait {
+        exit_with(result);
+    }
+}
+
+// Error of the launch to show in the preview, including a non-zero exit status of `--wait`
+fn launch_error(result: &Result<i32, String>) -> Option<String> {
+    match result {
+        Ok(0) => None,
+        Ok(code) => Some(format!("exited with status {}", code)),
+        Err(err) => Some(err.clone()),
+    }
+}
+
+// Exit with the status of the command run by `--wait`
+fn exit_with(result: Result<i32, String>) -> ! {
+    match result {
+        Ok(code) => std::process::exit(code),
+        Err(err) => {
+            eprintln!("Failed to launch command: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Words of the command run by the entry: the executable itself, or the expanded Exec
+fn entry_words(entry: &Entry) -> Result<Vec<String>, String> {
+    if entry.desktop {
+        let cmd = expand_field_codes(entry);
+        shlex::split(&cmd)
+            .ok_or_else(|| format!("Failed to parse command of {}: {}", entry.path, cmd))
+    } else {
+        Ok(vec![entry.path.clone()])
+    }
+}
+
+// Words of the command from the program on. `NAME=value` assignments before the program are
+// skipped, and so is `env` with its assignments unless it has options other than `-i`.
+fn program_words(words: &[String]) -> &[String] {
+    let mut words = words;
+    loop {
+        while words
+            .first()
+            .is_some_and(|word| RE_ASSIGNMENT.is_match(word))
+        {
+            words = &words[1..];
+        }
+        match words.first() {
+            Some(program) if program_name(program) == "env" => {
+                let mut rest = &words[1..];
+                while matches!(
+                    rest.first().map(String::as_str),
+                    Some("-i" | "--ignore-environment" | "-")
+                ) {
+                    rest = &rest[1..];
+                }
+                if rest.first().map(String::as_str) == Some("--") {
+                    rest = &rest[1..];
+                }
+                // `env` alone prints the environment
+                if rest.is_empty() || rest[0].starts_with('-') {
+                    return words;
+                }
+                words = rest;
+            }
+            _ => return words,
+        }
+    }
+}
+
+// Base name of the program
+fn program_name(program: &str) -> Cow<'_, str> {
+    Path::new(program)
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_else(|| program.into())
+}
+
+// Check the program of the command words against `--deny` and `--allow` patterns. Commands run
+// by wrappers other than `env` can't be told reliably, so they are refused when either is given.
+fn check_command_allowed(words: &[String], allow: &[Regex], deny: &[Regex]) -> Result<(), String> {
+    if allow.is_empty() && deny.is_empty() {
+        return Ok(());
+    }
+    let words = program_words(words);
+    let program = match words.first() {
+        Some(program) => program,
+        None => return Ok(()),
+    };
+    check_allowed(program, allow, deny)?;
+    if words.len() > 1 && WRAPPERS.contains(&program_name(program).as_ref()) {
+        return Err(format!(
+            "Refusing to launch command run by {}: {}",
+            program,
+            shlex::join(words.iter().map(String::as_str))
+        ));
+    }
+    Ok(())
+}
+
+// Check the program name against `--deny` and `--allow` patterns
+fn check_allowed(program: &str, allow: &[Regex], deny: &[Regex]) -> Result<(), String> {
+    let name = program_name(program);
+    if deny.iter().any(|pattern| pattern.is_match(&name)) {
+        return Err(format!("Refusing to launch denied command: {}", program));
+    }
+    if !allow.is_empty() && !allow.iter().any(|pattern| pattern.is_match(&name)) {
+        return Err(format!(
+            "Refusing to launch command not allowed: {}",
+            program
+        ));
+    }
+    Ok(())
+}
+
+// Check if the raw query contains characters with which `sh -c` runs more than the program of
+// its first word, like `;`, `&&`, `|`, `$(...)`, backticks, redirections or newlines
+fn has_shell_operators(cmd: &str) -> bool {
+    cmd.contains(|c| ";&|`$<>()\n\r".contains(c))
+}
+
+// Check the raw query against `--deny` and `--allow` patterns. Only the program is matched,
+// so queries with shell operators or which can't be parsed are refused when either is given.
+fn check_raw_allowed(cmd: &str, allow: &[Regex], deny: &[Regex]) -> Result<(), String> {
+    let restricted = !deny.is_empty() || !allow.is_empty();
+    if restricted && has_shell_operators(cmd) {
+        return Err(format!(
+            "Refusing to launch command with shell operators: {}",
+            cmd
+        ));
+    }
+    match shlex::split(cmd) {
+        Some(words) => check_command_allowed(&words, allow, deny),
+        None if restricted => Err(format!("Refusing to launch command not parsed: {}", cmd)),
+        None => Ok(()),
+    }
+}
+
+/// Check if the entry can be launched under `--deny` and `--allow` patterns
+pub fn is_launchable(entry: &Entry) -> bool {
+    entry_words(entry)
+        .and_then(|words| check_command_allowed(&words, &OPTIONS.allow, &OPTIONS.deny))
+        .is_ok()
+}
+
+// Check that the program run by the entry still exists
+fn check_exec_resolves(entry: &Entry) -> Result<(), String> {
+    let words = entry_words(entry)?;
+    let program = match program_words(&words).first() {
+        Some(program) => program,
+        None => return Err(format!("No command to run in {}", entry.path)),
+    };
+    match resolve_command(program) {
+        Some(_) => Ok(()),
+        None => Err(format!("Command not found or not executable: {}", program)),
+    }
+}
+
+// Check if desktop entries are launched with `gtk-launch` by `--use-gtk-launch`
+fn gtk_launch_enabled() -> bool {
+    match OPTIONS.use_gtk_launch {
+        Some(GtkLaunch::Always) => true,
+        Some(GtkLaunch::Auto) => {
+            env::var_os("WAYLAND_DISPLAY").is_some() && resolve_command("gtk-launch").is_some()
+        }
+        None => false,
+    }
+}
+
+// Shell command to launch the application of a desktop file by its ID with `gtk-launch`,
+// passing files and URLs of `--open` as URLs
+fn gtk_launch_command(entry: &Entry) -> Option<String> {
+    if !*USE_GTK_LAUNCH || OPTIONS.wait || !entry.is_desktop_app() {
+        return None;
+    }
+    let id = entry.id();
+    let mut args = vec!["gtk-launch", id.as_str()];
+    let urls: Vec<String> = OPEN_ARGS.iter().map(|a| to_url(a)).collect();
+    args.extend(urls.iter().map(String::as_str));
+    Some(apply_prefix(shlex::join(args), &OPTIONS))
+}
+
+// Build shell command to launch the entry
+fn build_command(entry: &Entry) -> String {
+    let cmd = if !entry.desktop {
+        // command from bin entry
+        entry.exec.trim().to_string()
     } else if !entry.terminal {
-        exec_app(entry);
+        // app from desktop entry, not terminal app
+        expand_field_codes(entry)
     } else {
-        exec_term(entry);
+        // terminal app from desktop entry
+        wrap_terminal(expand_field_codes(entry), &OPTIONS)
+    };
+    apply_prefix(cmd, &OPTIONS)
+}
+
+// Prepend `--command-prefix` and evaluate the result in `--prefix-shell` if specified
+fn apply_prefix(cmd: String, options: &Cli) -> String {
+    let cmd = match &options.command_prefix {
+        Some(prefix) => format!("{} {}", prefix, cmd),
+        None => cmd,
+    };
+    match &options.prefix_shell {
+        Some(shell) => format!("{} -ic {}", shlex::quote(shell), shlex::quote(&cmd)),
+        None => cmd,
+    }
+}
+
+// Print how the entry is turned into a command for `--dry-run`
+fn trace_entry(entry: &Entry) {
+    if entry.desktop {
+        eprintln!("source: desktop entry {}", entry.path);
+        eprintln!("exec: {}", entry.exec);
+        eprintln!("expanded: {}", expand_field_codes(entry));
+        if entry.terminal {
+            eprintln!(
+                "terminal: {}",
+                wrap_terminal(expand_field_codes(entry), &OPTIONS)
+            );
+        }
+    } else {
+        eprintln!("source: executable {}", entry.path);
+        eprintln!("exec: {}", entry.exec);
+    }
+    if OPTIONS.command_prefix.is_some() || OPTIONS.prefix_shell.is_some() {
+        eprintln!("prefixed: {}", build_command(entry));
+    }
+}
+
+// Print the command line which would be spawned for `--dry-run`
+fn dry_run(cmd: &str, entry: Option<&Entry>) {
+    match env::current_dir() {
+        Ok(dir) => eprintln!("working directory: {}", dir.display()),
+        Err(_) => eprintln!("working directory: (unknown)"),
+    }
+    for (name, value) in prime_env(entry) {
+        eprintln!("env: {}={}", name, value);
+    }
+    eprintln!("argv: {:?}", exec_args(cmd, &OPTIONS, *USE_SETSID));
+}
+
+// Variables to run the command on the discrete GPU with `--prime`, or with `--prime-auto` for
+// desktop entries which prefer it
+fn prime_env(entry: Option<&Entry>) -> &'static [(&'static str, &'static str)] {
+    let preferred = OPTIONS.prime_auto && entry.is_some_and(|e| e.prefers_non_default_gpu);
+    if !OPTIONS.prime && !preferred {
+        return &[];
+    }
+    match OPTIONS.prime_vendor.unwrap_or(PrimeVendor::Mesa) {
+        PrimeVendor::Mesa => &[("DRI_PRIME", "1")],
+        PrimeVendor::Nvidia => &[
+            ("__NV_PRIME_RENDER_OFFLOAD", "1"),
+            ("__GLX_VENDOR_LIBRARY_NAME", "nvidia"),
+        ],
+    }
+}
+
+// Variables applied to the launched command: the login environment with `--session-env` for
+// desktop entries, and variables of `--prime`
+fn launch_env(entry: Option<&Entry>) -> Vec<(OsString, OsString)> {
+    let mut vars = Vec::new();
+    if OPTIONS.session_env && entry.is_some_and(|e| e.desktop) {
+        vars.extend(SESSION_ENV.iter().cloned());
+    }
+    vars.extend(
+        prime_env(entry)
+            .iter()
+            .map(|&(name, value)| (name.into(), value.into())),
+    );
+    vars
+}
+
+// Decode `%XX` escapes of a URL
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        // `from_str_radix` accepts a sign, so both chars are checked to be hex digits
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Escape characters of a path which are not allowed in a URL as `%XX`
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// Files and URLs to open with the entry: those of `--open`, then the clipboard content of
+// `--open-clipboard`
+fn get_open_args() -> Vec<String> {
+    let mut args = OPTIONS.open.clone();
+    if OPTIONS.open_clipboard {
+        if let Ok(clipboard) = &*CLIPBOARD {
+            args.push(clipboard.arg.clone());
+        }
     }
+    args
 }
 
-// Execute command from bin entry
-fn exec_command(entry: Entry) {
-    let cmd = entry.exec.trim();
-    _exec(cmd);
+/// Convert an `--open` argument to a local path for `%f`/`%F`.
+/// `file://` URLs are converted to paths, and other URLs are skipped.
+pub fn to_local_path(arg: &str) -> Option<String> {
+    match arg.split_once("://") {
+        Some((scheme, rest)) if scheme.eq_ignore_ascii_case("file") => {
+            // drop the host part, which is empty or `localhost` for local files
+            let path = &rest[rest.find('/')?..];
+            Some(percent_decode(path))
+        }
+        Some(_) => None,
+        None => Some(arg.to_string()),
+    }
+}
+
+// Convert an `--open` argument to a URL for `%u`/`%U`. Local paths become `file://` URLs.
+fn to_url(arg: &str) -> String {
+    if arg.contains("://") {
+        return arg.to_string();
+    }
+    let path = Path::new(arg);
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().unwrap_or_default().join(path)
+    };
+    format!("file://{}", percent_encode(&path.to_string_lossy()))
 }
 
-// Run app from desktop entry, not terminal app
-fn exec_app(entry: Entry) {
-    let cmd = entry.exec.trim();
-    _exec(&RE_EXEC_OPT.replace_all(cmd, ""));
+// Expand field codes of the Exec key of a desktop entry.
+// `%c` (name) and `%k` (file path) are expanded, `%%` becomes `%`, files and URLs of `--open`
+// are passed by `%f`/`%F`/`%u`/`%U`, and other codes are removed.
+fn expand_field_codes(entry: &Entry) -> String {
+    let quote_all = |args: Vec<String>| shlex::join(args.iter().map(String::as_str));
+    RE_FIELD_CODE
+        .replace_all(entry.exec.trim(), |caps: &regex::Captures| {
+            let space = &caps[1];
+            let args = match &caps[2] {
+                "%" => return format!("{}%", space),
+                "c" => return format!("{}{}", space, shlex::quote(&entry.name)),
+                "k" => return format!("{}{}", space, shlex::quote(&entry.path)),
+                "f" => OPEN_ARGS
+                    .iter()
+                    .filter_map(|a| to_local_path(a))
+                    .take(1)
+                    .collect(),
+                "F" => OPEN_ARGS.iter().filter_map(|a| to_local_path(a)).collect(),
+                "u" => OPEN_ARGS.iter().map(|a| to_url(a)).take(1).collect(),
+                "U" => OPEN_ARGS.iter().map(|a| to_url(a)).collect(),
+                _ => Vec::new(),
+            };
+            if args.is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", space, quote_all(args))
+            }
+        })
+        .into_owned()
 }
 
-// Run terminal app from desktop entry
-fn exec_term(entry: Entry) {
-    let cmd = RE_EXEC_OPT.replace_all(entry.exec.trim(), "").into_owned();
+// Option of the terminal program to keep its window open after the command exits
+fn terminal_hold_flag(terminal: &str) -> Option<&'static str> {
+    let name = Path::new(terminal).file_name()?.to_str()?;
+    match name {
+        "foot" | "alacritty" | "kitty" | "konsole" | "xfce4-terminal" => Some("--hold"),
+        "xterm" | "urxvt" | "rxvt" => Some("-hold"),
+        _ => None,
+    }
+}
+
+// Options of the terminal program to set the class (app-id on Wayland) of its window
+fn terminal_class_args(terminal: &str, class: &str) -> Option<Vec<String>> {
+    let name = Path::new(terminal).file_name()?.to_str()?;
+    let args = match name {
+        "alacritty" | "kitty" => vec!["--class".to_string(), class.to_string()],
+        "foot" => vec!["--app-id".to_string(), class.to_string()],
+        "ghostty" => vec![format!("--class={}", class)],
+        "xterm" => vec!["-class".to_string(), class.to_string()],
+        "urxvt" | "rxvt" => vec!["-name".to_string(), class.to_string()],
+        "st" => vec!["-c".to_string(), class.to_string()],
+        _ => return None,
+    };
+    Some(args)
+}
 
+// Wrap command to run it in a terminal
+fn wrap_terminal(cmd: String, options: &Cli) -> String {
     let mut term_cmd: Vec<String> = Vec::new();
-    match &OPTIONS.terminal_command {
+    match &options.terminal_command {
         Some(val) => {
-            term_cmd.extend(shlex::split(&val).expect("Failed to parse --terminal-command option"))
+            term_cmd.extend(shlex::split(val).expect("Failed to parse --terminal-command option"))
         }
         None => match env::var_os("TERM") {
             Some(val) => term_cmd = vec![val.to_str().unwrap().to_string(), "-e".to_string()],
             None => term_cmd = vec!["alacritty".to_string(), "-e".to_string()],
         },
     }
-    term_cmd.push(cmd);
+    if let Some(class) = &options.terminal_class {
+        if let Some(args) = term_cmd.first().and_then(|t| terminal_class_args(t, class)) {
+            term_cmd.splice(1..1, args);
+        }
+    }
+    let mut cmd = cmd;
+    if let Some(strategy) = options.terminal_hold {
+        let flag = match strategy {
+            TerminalHold::Shell => None,
+            _ => term_cmd.first().and_then(|t| terminal_hold_flag(t)),
+        };
+        match flag {
+            Some(flag) => term_cmd.insert(1, flag.to_string()),
+            None if strategy == TerminalHold::Flag => {
+                eprintln!("Hold option of the terminal is unknown, the terminal is not held")
+            }
+            None => {
+                let held = format!("{}; printf '\\n[Press Enter to close]'; read _", cmd);
+                cmd = format!("sh -c {}", shlex::quote(&held));
+            }
+        }
+    }
+    // pass each word as a separate argument so that multi-word commands like
+    // `flatpak run org.example.App` or `env VAR=1 app` run as they would without a terminal
+    match shlex::split(&cmd) {
+        Some(words) if !words.is_empty() => term_cmd.extend(words),
+        _ => term_cmd.push(cmd),
+    }
 
     // convert Vec<String> to Iter<&str> and join to a single String
-    let command = shlex::join(term_cmd.iter().map(String::as_str));
-    _exec(&command);
+    shlex::join(term_cmd.iter().map(String::as_str))
+}
+
+// Arguments of the process spawned to run the shell command
+fn exec_args(cmd: &str, options: &Cli, setsid: bool) -> Vec<String> {
+    if let Some(template) = &options.exec_template {
+        // validated when parsing options
+        let words = shlex::split(template).unwrap_or_default();
+        return words.iter().map(|w| w.replace("{cmd}", cmd)).collect();
+    }
+    let mut args = Vec::new();
+    if setsid {
+        args.push("setsid".to_string());
+    }
+    args.push("sh".to_string());
+    args.push(if options.login_shell { "-lc" } else { "-c" }.to_string());
+    args.push(cmd.to_string());
+    args
+}
+
+// Run command in the foreground for `--wait` and return its exit code.
+// A command killed by a signal is regarded as exiting with 128 + the signal number.
+fn exec_wait(cmd: &str, vars: &[(OsString, OsString)]) -> Result<i32, String> {
+    let args = exec_args(cmd, &OPTIONS, *USE_SETSID);
+    let mut command = Command::new(&args[0]);
+    command.args(&args[1..]).envs(vars.iter().cloned());
+    let status = command
+        .status()
+        .map_err(|e| format!("failed to start command: {}", e))?;
+    Ok(status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1))
 }
 
-fn _exec(cmd: &str) {
-    Command::new("setsid")
-        .arg("sh")
-        .arg("-c")
-        .arg(cmd)
+// Spawn command detached by `setsid`, or in a new process group without it.
+// Variables in `vars` are set in the environment of the command.
+// With `fail_fast`, a command which exits with an error right after spawning is reported as a
+// failed launch. Otherwise the process outlives the launcher, so it is not waited any longer.
+fn _exec(cmd: &str, vars: &[(OsString, OsString)], fail_fast: bool) -> Result<(), String> {
+    let args = exec_args(cmd, &OPTIONS, *USE_SETSID);
+    let mut command = Command::new(&args[0]);
+    command
+        .args(&args[1..])
+        .envs(vars.iter().cloned())
         .stdin(Stdio::null())
         .stdout(Stdio::null())
-        .stderr(Stdio::null())
+        .stderr(Stdio::null());
+    if OPTIONS.exec_template.is_some() || !*USE_SETSID {
+        // keep the process out of the terminal's process group so it survives closing it
+        command.process_group(0);
+    }
+    let mut child = command
         .spawn()
-        .expect("Failed to start command");
+        .map_err(|e| format!("failed to start command: {}", e))?;
+    if !fail_fast {
+        return Ok(());
+    }
+
+    let deadline = Instant::now() + FAIL_FAST_PERIOD;
+    while Instant::now() < deadline {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(format!("`{}` {}", cmd, status)),
+            Ok(None) => thread::sleep(Duration::from_millis(10)),
+            Err(_) => break,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn patterns(values: &[&str]) -> Vec<Regex> {
+        values.iter().map(|v| Regex::new(v).unwrap()).collect()
+    }
+
+    #[test]
+    fn deny_refuses_matching_program() {
+        let deny = patterns(&["^(rm|dd)$"]);
+        assert!(check_allowed("rm", &[], &deny).is_err());
+        assert!(check_allowed("/usr/bin/dd", &[], &deny).is_err());
+        assert!(check_allowed("firefox", &[], &deny).is_ok());
+    }
+
+    #[test]
+    fn allow_accepts_only_matching_program() {
+        let allow = patterns(&["^fire"]);
+        assert!(check_allowed("/usr/bin/firefox", &allow, &[]).is_ok());
+        assert!(check_allowed("vim", &allow, &[]).is_err());
+        // deny takes precedence
+        assert!(check_allowed("firefox", &allow, &patterns(&["fox"])).is_err());
+    }
+
+    #[test]
+    fn raw_query_checked_against_allow() {
+        let allow = patterns(&["^echo$"]);
+        assert!(check_raw_allowed("echo 'hello world'", &allow, &[]).is_ok());
+        assert!(check_raw_allowed("LANG=C echo hi", &allow, &[]).is_ok());
+        assert!(check_raw_allowed("rm -rf tmp", &allow, &[]).is_err());
+        assert!(check_raw_allowed("echo hi | sh", &allow, &[]).is_err());
+        assert!(check_raw_allowed("sudo echo hi", &patterns(&["^(sudo|echo)$"]), &[]).is_err());
+    }
+
+    #[test]
+    fn raw_query_checked_by_program() {
+        let deny = patterns(&["^rm$"]);
+        assert!(check_raw_allowed("rm -rf tmp", &[], &deny).is_err());
+        assert!(check_raw_allowed("echo 'rm'", &[], &deny).is_ok());
+        assert!(check_raw_allowed("rm -rf tmp", &[], &[]).is_ok());
+    }
+
+    #[test]
+    fn program_found_after_assignments_and_env() {
+        let deny = patterns(&["^rm$"]);
+        for cmd in [
+            "FOO=1 rm -rf tmp",
+            "env rm -rf tmp",
+            "env -i FOO=1 BAR=2 /bin/rm -rf tmp",
+            "FOO=1 env -- env rm tmp",
+        ] {
+            assert!(check_raw_allowed(cmd, &[], &deny).is_err(), "{:?}", cmd);
+        }
+        assert!(check_raw_allowed("FOO=1 env BAR=2 firefox", &[], &deny).is_ok());
+
+        let entry = app("Remove", "env GDK_BACKEND=x11 rm %f");
+        let words = entry_words(&entry).unwrap();
+        assert_eq!(program_words(&words), ["rm"]);
+        assert!(check_command_allowed(&words, &[], &deny).is_err());
+    }
+
+    #[test]
+    fn commands_of_wrappers_refused_when_restricted() {
+        let deny = patterns(&["^rm$"]);
+        for cmd in [
+            "sudo rm -rf tmp",
+            "nice -n 10 rm tmp",
+            "sh -c 'rm tmp'",
+            // options of `env` which take values are not followed
+            "env -u HOME rm tmp",
+        ] {
+            assert!(check_raw_allowed(cmd, &[], &deny).is_err(), "{:?}", cmd);
+            assert!(check_raw_allowed(cmd, &[], &[]).is_ok(), "{:?}", cmd);
+        }
+        // wrappers without a command run nothing else
+        assert!(check_raw_allowed("bash", &[], &deny).is_ok());
+        assert!(check_raw_allowed("env", &[], &deny).is_ok());
+    }
+
+    #[test]
+    fn raw_query_with_shell_operators_refused_when_restricted() {
+        let deny = patterns(&["^rm$"]);
+        for cmd in [
+            "echo hi; rm -rf tmp",
+            "echo hi && rm x",
+            "echo hi | sh",
+            "echo $(rm x)",
+            "echo `rm x`",
+            "echo hi > file",
+            "echo hi\nrm x",
+        ] {
+            assert!(check_raw_allowed(cmd, &[], &deny).is_err(), "{:?}", cmd);
+            assert!(check_raw_allowed(cmd, &[], &[]).is_ok(), "{:?}", cmd);
+        }
+    }
+
+    #[test]
+    fn raw_query_not_parsed_refused_when_restricted() {
+        let deny = patterns(&["^rm$"]);
+        assert!(check_raw_allowed("echo 'unclosed", &[], &deny).is_err());
+        assert!(check_raw_allowed("echo 'unclosed", &[], &[]).is_ok());
+    }
+
+    fn app(name: &str, exec: &str) -> Entry {
+        let mut entry = Entry::new();
+        entry.desktop = true;
+        entry.name = name.to_string();
+        entry.exec = exec.to_string();
+        entry
+    }
+
+    #[test]
+    fn failed_exit_status_recorded_as_error() {
+        assert_eq!(launch_error(&Ok(0)), None);
+        assert_eq!(
+            launch_error(&Ok(2)),
+            Some("exited with status 2".to_string())
+        );
+        assert_eq!(
+            launch_error(&Err("No such file".to_string())),
+            Some("No such file".to_string())
+        );
+    }
+
+    #[test]
+    fn prefix_prepended_literally_or_evaluated_by_shell() {
+        let options = Cli::parse_from(["sklauncher", "--command-prefix", "proxychains"]);
+        let cmd = "firefox 'a b'".to_string();
+        assert_eq!(
+            apply_prefix(cmd.clone(), &options),
+            "proxychains firefox 'a b'"
+        );
+        let options = Cli::parse_from([
+            "sklauncher",
+            "--command-prefix",
+            "proxychains",
+            "--prefix-shell",
+            "zsh",
+        ]);
+        let shell_cmd = apply_prefix(cmd, &options);
+        assert_eq!(
+            shlex::split(&shell_cmd).unwrap(),
+            ["zsh", "-ic", "proxychains firefox 'a b'"]
+        );
+        assert_eq!(
+            apply_prefix("ls".to_string(), &Cli::parse_from(["sklauncher"])),
+            "ls"
+        );
+    }
+
+    #[test]
+    fn wrapper_commands_kept_as_words_in_terminal() {
+        let options = Cli::parse_from(["sklauncher", "--terminal-command", "xterm -e"]);
+        for (exec, words) in [
+            (
+                "env GDK_BACKEND=x11 app %u",
+                vec!["env", "GDK_BACKEND=x11", "app"],
+            ),
+            (
+                "flatpak run org.example.App %U",
+                vec!["flatpak", "run", "org.example.App"],
+            ),
+            (
+                "pkexec /usr/bin/app --name %c",
+                vec!["pkexec", "/usr/bin/app", "--name", "My App"],
+            ),
+        ] {
+            let cmd = wrap_terminal(expand_field_codes(&app("My App", exec)), &options);
+            let expected: Vec<&str> = ["xterm", "-e"].into_iter().chain(words).collect();
+            assert_eq!(shlex::split(&cmd).unwrap(), expected, "{:?}", exec);
+        }
+    }
+
+    #[test]
+    fn hold_flag_of_terminal() {
+        for (terminal, flag) in [
+            ("foot", Some("--hold")),
+            ("/usr/bin/alacritty", Some("--hold")),
+            ("kitty", Some("--hold")),
+            ("konsole", Some("--hold")),
+            ("xfce4-terminal", Some("--hold")),
+            ("xterm", Some("-hold")),
+            ("urxvt", Some("-hold")),
+            ("rxvt", Some("-hold")),
+            ("gnome-terminal", None),
+            ("st", None),
+        ] {
+            assert_eq!(terminal_hold_flag(terminal), flag, "{:?}", terminal);
+        }
+    }
+
+    #[test]
+    fn held_command_assembled_by_strategy() {
+        let wrap = |terminal: &str, strategy: &str| {
+            let options = Cli::parse_from([
+                "sklauncher",
+                "--terminal-command",
+                terminal,
+                "--terminal-hold",
+                strategy,
+            ]);
+            shlex::split(&wrap_terminal("htop -d 10".to_string(), &options)).unwrap()
+        };
+        let held = "htop -d 10; printf '\\n[Press Enter to close]'; read _";
+        assert_eq!(
+            wrap("xterm -e", "auto"),
+            ["xterm", "-hold", "-e", "htop", "-d", "10"]
+        );
+        assert_eq!(
+            wrap("gnome-terminal --", "auto"),
+            ["gnome-terminal", "--", "sh", "-c", held]
+        );
+        assert_eq!(wrap("xterm -e", "shell"), ["xterm", "-e", "sh", "-c", held]);
+        // the command is not held without a known flag
+        assert_eq!(
+            wrap("gnome-terminal --", "flag"),
+            ["gnome-terminal", "--", "htop", "-d", "10"]
+        );
+    }
+
+    #[test]
+    fn percent_escapes_decoded_only_with_hex_digits() {
+        assert_eq!(percent_decode("/a%20b%2Fc"), "/a b/c");
+        assert_eq!(percent_decode("/100%+1"), "/100%+1");
+        assert_eq!(percent_decode("/a%-1b%zz%2"), "/a%-1b%zz%2");
+        assert_eq!(percent_decode("/caf%C3%A9"), "/caf\u{e9}");
+    }
+
+    #[test]
+    fn local_paths_and_urls_round_trip() {
+        for path in [
+            "/tmp/a b.txt",
+            "/tmp/100% sure",
+            "/tmp/caf\u{e9}/\u{65e5}\u{672c}.txt",
+        ] {
+            let url = to_url(path);
+            assert!(url.starts_with("file:///tmp/"), "{:?}", url);
+            assert!(!url.contains(' '), "{:?}", url);
+            assert_eq!(to_local_path(&url).as_deref(), Some(path));
+        }
+        assert_eq!(to_url("/tmp/a b"), "file:///tmp/a%20b");
+        assert_eq!(
+            to_local_path("file://localhost/tmp/a%20b").as_deref(),
+            Some("/tmp/a b")
+        );
+        // URLs other than files are passed as URLs only
+        let url = "https://example.com/a%20b?q=1";
+        assert_eq!(to_url(url), url);
+        assert_eq!(to_local_path(url), None);
+        // relative paths are resolved in the current directory
+        let cwd = env::current_dir().unwrap();
+        assert_eq!(
+            to_local_path(&to_url("a b")).map(PathBuf::from),
+            Some(cwd.join("a b"))
+        );
+    }
+
+    #[test]
+    fn exec_template_substituted_with_command() {
+        let cmd = "firefox '/tmp/a b.html'";
+        let options = Cli::parse_from([
+            "sklauncher",
+            "--exec-template",
+            "systemd-run --user --scope --description 'launched app' sh -c {cmd}",
+        ]);
+        assert_eq!(
+            exec_args(cmd, &options, true),
+            [
+                "systemd-run",
+                "--user",
+                "--scope",
+                "--description",
+                "launched app",
+                "sh",
+                "-c",
+                cmd
+            ]
+        );
+        let options = Cli::parse_from(["sklauncher"]);
+        assert_eq!(exec_args(cmd, &options, true), ["setsid", "sh", "-c", cmd]);
+        let options = Cli::parse_from(["sklauncher", "--login-shell"]);
+        assert_eq!(exec_args(cmd, &options, false), ["sh", "-lc", cmd]);
+        assert!(Cli::try_parse_from(["sklauncher", "--exec-template", "nohup sh"]).is_err());
+    }
+
+    #[test]
+    fn class_args_of_terminal() {
+        let args = |terminal: &str| terminal_class_args(terminal, "htop-term");
+        for (terminal, expected) in [
+            ("alacritty", vec!["--class", "htop-term"]),
+            ("/usr/bin/kitty", vec!["--class", "htop-term"]),
+            ("foot", vec!["--app-id", "htop-term"]),
+            ("ghostty", vec!["--class=htop-term"]),
+            ("xterm", vec!["-class", "htop-term"]),
+            ("urxvt", vec!["-name", "htop-term"]),
+            ("rxvt", vec!["-name", "htop-term"]),
+            ("st", vec!["-c", "htop-term"]),
+        ] {
+            assert_eq!(args(terminal).unwrap(), expected, "{:?}", terminal);
+        }
+        assert_eq!(args("gnome-terminal"), None);
+    }
+
+    #[test]
+    fn class_args_inserted_after_terminal() {
+        let wrap = |terminal: &str, hold: bool| {
+            let mut args = vec![
+                "sklauncher",
+                "--terminal-command",
+                terminal,
+                "--terminal-class",
+                "htop-term",
+            ];
+            if hold {
+                args.push("--terminal-hold");
+            }
+            shlex::split(&wrap_terminal("htop".to_string(), &Cli::parse_from(args))).unwrap()
+        };
+        assert_eq!(
+            wrap("foot", true),
+            ["foot", "--hold", "--app-id", "htop-term", "htop"]
+        );
+        assert_eq!(
+            wrap("xterm -e", false),
+            ["xterm", "-class", "htop-term", "-e", "htop"]
+        );
+        // unknown terminals are run as given
+        assert_eq!(
+            wrap("gnome-terminal --", false),
+            ["gnome-terminal", "--", "htop"]
+        );
+    }
+
+    #[test]
+    fn name_field_code_expanded_as_one_argument() {
+        let entry = app("My App", "wrapper %c");
+        assert_eq!(expand_field_codes(&entry), "wrapper \"My App\"");
+    }
+
+    #[test]
+    fn percent_field_code_expanded_to_percent() {
+        let entry = app("My App", "printf 100%% %c");
+        assert_eq!(expand_field_codes(&entry), "printf 100% \"My App\"");
+        // other field codes are removed with the space before them
+        let entry = app("My App", "app %i --flag");
+        assert_eq!(expand_field_codes(&entry), "app --flag");
+    }
 }
@@ -1,34 +1,219 @@
 use std::fs;
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use indexmap::map::IndexMap;
 
-use crate::entry::Entry;
+use crate::entry::{now, Entry, OPTIONS};
 
-fn get_hist_file() -> PathBuf {
+// Period of `--history-decay`
+const DECAY_PERIOD: u64 = 24 * 60 * 60;
+
+lazy_static! {
+    // tests keep the history only in memory, not to touch the history of the user
+    static ref HIST_FILE: Option<PathBuf> = if cfg!(test) { None } else { get_hist_file() };
+}
+
+// Prepare the history file. If the cache directory or the file can't be written, warn and
+// return `None` so that the history is kept only in memory for this run.
+fn get_hist_file() -> Option<PathBuf> {
     let base = xdg::BaseDirectories::with_prefix("sklauncher").unwrap();
     let cache_dir = base.get_cache_home();
-    if !cache_dir.is_dir() {
-        fs::create_dir_all(cache_dir.as_path()).unwrap();
+    let hist_file = match &OPTIONS.profile {
+        Some(profile) => cache_dir.join(format!("history-{}.toml", profile)),
+        None => cache_dir.join("history.toml"),
+    };
+    let writable = fs::create_dir_all(cache_dir.as_path()).and_then(|_| {
+        fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(hist_file.as_path())
+    });
+    match writable {
+        Ok(_) => Some(hist_file),
+        Err(err) => {
+            eprintln!(
+                "History file {} is not writable ({}), history is not saved",
+                hist_file.display(),
+                err
+            );
+            None
+        }
     }
-    let hist_file = cache_dir.join("history.toml");
-    if !hist_file.is_file() {
-        fs::write(hist_file.as_path(), b"").unwrap();
-    }
-    hist_file
 }
 
 pub fn load_history() -> IndexMap<String, Entry> {
-    let contents = fs::read_to_string(get_hist_file()).expect("Failed to open history file");
+    let hist_file = match HIST_FILE.as_ref() {
+        Some(hist_file) => hist_file,
+        None => return IndexMap::new(),
+    };
+    let contents = fs::read_to_string(hist_file).expect("Failed to open history file");
     toml::from_str::<IndexMap<String, Entry>>(&contents).expect("History file is broken")
 }
 
-pub fn save_history(history: &IndexMap<String, Entry>) {
-    let hist_file_path = get_hist_file();
+// Multiply launch counts by `factor` (`--history-decay`) for each day passed since the previous decay.
+// The time of the previous decay is kept in a file next to the history file.
+fn decay_history(history: &mut IndexMap<String, Entry>, hist_file: &Path, factor: f64) {
+    if factor >= 1.0 {
+        return;
+    }
+    let stamp_file = hist_file.with_extension("decay");
+    let now = now();
+    let last_decay = fs::read_to_string(&stamp_file)
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok());
+    let stamp = match last_decay {
+        Some(last_decay) => {
+            let days = now.saturating_sub(last_decay) / DECAY_PERIOD;
+            if days == 0 {
+                return;
+            }
+            let scale = factor.powi(days.min(i32::MAX as u64) as i32);
+            for entry in history.values_mut() {
+                entry.count = (entry.count as f64 * scale) as u32;
+            }
+            last_decay + days * DECAY_PERIOD
+        }
+        // first run with decay enabled, start counting days from now
+        None => now,
+    };
+    fs::write(stamp_file, stamp.to_string()).expect("Failed to write history decay file");
+}
+
+pub fn save_history(history: &mut IndexMap<String, Entry>) {
+    let hist_file_path = match HIST_FILE.as_ref() {
+        Some(hist_file) => hist_file,
+        None => return,
+    };
+    decay_history(history, hist_file_path, OPTIONS.history_decay);
     let mut file = fs::File::create(hist_file_path).expect("Failed to open history file");
     let contents = toml::to_string::<IndexMap<String, Entry>>(history)
         .expect("Failed convert history to toml format");
-    file.write(contents.as_bytes())
+    file.write_all(contents.as_bytes())
         .expect("Failed to write history file");
 }
+
+/// Clear the history together with the query saved by `--remember-query` and the time of the
+/// previous `--history-decay`. Returns the number of entries cleared.
+pub fn clear_history() -> usize {
+    let cleared = load_history().len();
+    save_history(&mut IndexMap::new());
+    if let Some(hist_file) = HIST_FILE.as_ref() {
+        for extension in ["query", "decay"] {
+            drop(fs::remove_file(hist_file.with_extension(extension)));
+        }
+    }
+    cleared
+}
+
+/// Reset the launch count of the entry to zero and save the history
+pub fn reset_count(key: &str, history: &mut IndexMap<String, Entry>) {
+    if let Some(entry) = history.get_mut(key) {
+        entry.count = 0;
+        save_history(history);
+    }
+}
+
+// File of the last query for `--remember-query`, kept next to the history file
+fn query_file() -> Option<PathBuf> {
+    HIST_FILE
+        .as_ref()
+        .map(|hist_file| hist_file.with_extension("query"))
+}
+
+/// Load the query saved by the previous run
+pub fn load_last_query() -> Option<String> {
+    let query = fs::read_to_string(query_file()?).ok()?;
+    Some(query).filter(|q| !q.trim().is_empty())
+}
+
+/// Save the query for the next run. Empty queries are not saved.
+pub fn save_last_query(query: &str) {
+    if query.trim().is_empty() {
+        return;
+    }
+    if let Some(query_file) = query_file() {
+        fs::write(query_file, query).expect("Failed to write query file");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(counts: &[(&str, u32)]) -> IndexMap<String, Entry> {
+        counts
+            .iter()
+            .map(|(key, count)| {
+                let mut entry = Entry::new();
+                entry.path = key.to_string();
+                entry.count = *count;
+                (key.to_string(), entry)
+            })
+            .collect()
+    }
+
+    // History file in an empty directory under the system temporary directory
+    fn temp_hist_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sklauncher-{}-{}", std::process::id(), name));
+        drop(fs::remove_dir_all(&dir));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("history.toml")
+    }
+
+    #[test]
+    fn decay_starts_counting_days_on_first_run() {
+        let hist_file = temp_hist_file("decay-first");
+        let mut history = history(&[("a", 8)]);
+        decay_history(&mut history, &hist_file, 0.5);
+        assert_eq!(history["a"].count, 8);
+        let stamp: u64 = fs::read_to_string(hist_file.with_extension("decay"))
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(now() - stamp < DECAY_PERIOD);
+        fs::remove_dir_all(hist_file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn decay_applies_per_whole_day() {
+        let hist_file = temp_hist_file("decay-days");
+        let stamp_file = hist_file.with_extension("decay");
+        // two days and a half ago
+        let last_decay = now() - DECAY_PERIOD * 5 / 2;
+        fs::write(&stamp_file, last_decay.to_string()).unwrap();
+        let mut history = history(&[("a", 8), ("b", 1)]);
+        decay_history(&mut history, &hist_file, 0.5);
+        assert_eq!(history["a"].count, 2);
+        assert_eq!(history["b"].count, 0);
+        // the rest of the day is carried over to the next decay
+        let stamp = fs::read_to_string(&stamp_file).unwrap();
+        assert_eq!(stamp, (last_decay + DECAY_PERIOD * 2).to_string());
+
+        // decayed at most once a day
+        decay_history(&mut history, &hist_file, 0.5);
+        assert_eq!(history["a"].count, 2);
+        fs::remove_dir_all(hist_file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn decay_disabled_by_default() {
+        let hist_file = temp_hist_file("decay-off");
+        let mut history = history(&[("a", 8)]);
+        decay_history(&mut history, &hist_file, 1.0);
+        assert_eq!(history["a"].count, 8);
+        assert!(!hist_file.with_extension("decay").exists());
+        fs::remove_dir_all(hist_file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn reset_count_of_entry() {
+        let mut history = history(&[("a", 5), ("b", 3)]);
+        reset_count("a", &mut history);
+        assert_eq!(history["a"].count, 0);
+        assert_eq!(history["b"].count, 3);
+        // unknown keys are ignored
+        reset_count("c", &mut history);
+        assert_eq!(history.len(), 2);
+    }
+}
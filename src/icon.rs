@@ -0,0 +1,138 @@
+use std::collections::{HashSet, VecDeque};
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::entry::OPTIONS;
+
+lazy_static! {
+    static ref ICON_BASE_DIRS: Vec<PathBuf> = get_icon_base_dirs();
+    static ref ICON_THEME_DIRS: Vec<PathBuf> = get_icon_theme_dirs();
+}
+
+// Extensions of icon files in the order of preference
+const ICON_EXTENSIONS: [&str; 3] = ["png", "svg", "xpm"];
+
+// Theme every icon theme implicitly inherits from
+const FALLBACK_THEME: &str = "hicolor";
+
+// Base directories of icon themes: `~/.icons`, then `icons` under the XDG data directories
+fn get_icon_base_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(home) = env::var_os("HOME") {
+        dirs.push(Path::new(&home).join(".icons"));
+    }
+    if let Ok(base) = xdg::BaseDirectories::with_prefix("icons") {
+        dirs.push(base.get_data_home());
+        dirs.extend(base.get_data_dirs());
+    }
+    dirs.into_iter().filter(|d| d.is_dir()).collect()
+}
+
+// Name of the icon theme in the GTK or KDE settings
+fn get_default_theme() -> Option<String> {
+    let base = xdg::BaseDirectories::new().ok()?;
+    for file in ["gtk-4.0/settings.ini", "gtk-3.0/settings.ini"] {
+        let theme = base
+            .find_config_file(file)
+            .and_then(|path| ini::Ini::load_from_file(path).ok())
+            .and_then(|conf| {
+                conf.get_from(Some("Settings"), "gtk-icon-theme-name")
+                    .map(str::to_string)
+            });
+        if theme.is_some() {
+            return theme;
+        }
+    }
+    base.find_config_file("kdeglobals")
+        .and_then(|path| ini::Ini::load_from_file(path).ok())
+        .and_then(|conf| conf.get_from(Some("Icons"), "Theme").map(str::to_string))
+}
+
+// Subdirectories of the theme listed in its `index.theme` in all base directories, and the names
+// of the themes it inherits from
+fn load_theme(name: &str) -> Option<(Vec<PathBuf>, Vec<String>)> {
+    let index = ICON_BASE_DIRS
+        .iter()
+        .map(|base| base.join(name).join("index.theme"))
+        .find(|index| index.is_file())?;
+    let conf = ini::Ini::load_from_file(index).ok()?;
+    let section = conf.section(Some("Icon Theme"))?;
+    let list = |key: &str| -> Vec<String> {
+        section
+            .get(key)
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let subdirs: Vec<String> = list("Directories")
+        .into_iter()
+        .chain(list("ScaledDirectories"))
+        .collect();
+    let dirs = ICON_BASE_DIRS
+        .iter()
+        .map(|base| base.join(name))
+        .filter(|dir| dir.is_dir())
+        .flat_map(|dir| subdirs.iter().map(move |subdir| dir.join(subdir)))
+        .filter(|dir| dir.is_dir())
+        .collect();
+    Some((dirs, list("Inherits")))
+}
+
+// Directories searched for icons: those of `--icon-theme` (or the theme of the desktop settings)
+// and the themes it inherits from in breadth-first order, then `hicolor`
+fn get_icon_theme_dirs() -> Vec<PathBuf> {
+    let theme = OPTIONS
+        .icon_theme
+        .clone()
+        .or_else(get_default_theme)
+        .unwrap_or_else(|| FALLBACK_THEME.to_string());
+    let mut dirs = Vec::new();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::from([theme]);
+    while let Some(name) = queue.pop_front() {
+        // `hicolor` is searched after all other themes
+        if name == FALLBACK_THEME || !visited.insert(name.clone()) {
+            continue;
+        }
+        if let Some((theme_dirs, inherits)) = load_theme(&name) {
+            dirs.extend(theme_dirs);
+            queue.extend(inherits);
+        }
+    }
+    if let Some((theme_dirs, _)) = load_theme(FALLBACK_THEME) {
+        dirs.extend(theme_dirs);
+    }
+    dirs
+}
+
+/// Find the file of the icon of the `Icon` key. An absolute path is used as is if the file exists.
+/// Otherwise the icon is looked up by name in the icon themes, falling back to icons which are
+/// not in any theme. The first file found in the order of theme directories is used regardless
+/// of size.
+pub fn resolve_icon(icon: &str) -> Option<PathBuf> {
+    let path = Path::new(icon);
+    if path.is_absolute() {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    // names should not have an extension, but some desktop files add it anyway
+    let name = ICON_EXTENSIONS
+        .iter()
+        .find_map(|ext| icon.strip_suffix(&format!(".{}", ext)))
+        .unwrap_or(icon);
+    let unthemed = ICON_BASE_DIRS
+        .iter()
+        .cloned()
+        .chain([PathBuf::from("/usr/share/pixmaps")]);
+    ICON_THEME_DIRS
+        .iter()
+        .cloned()
+        .chain(unthemed)
+        .flat_map(|dir| ICON_EXTENSIONS.map(|ext| dir.join(format!("{}.{}", name, ext))))
+        .find(|path| path.is_file())
+}
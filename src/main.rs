@@ -1,33 +1,229 @@
 #[macro_use]
 extern crate lazy_static;
 
+use std::collections::HashSet;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use indexmap::IndexMap;
+use serde::Serialize;
 use skim::prelude::*;
 
+mod clipboard;
 mod entry;
 mod exec;
 mod history;
+mod icon;
 mod options;
+mod preview;
+mod stdin;
+mod validate;
+
+use clipboard::{handles_clipboard, CLIPBOARD};
+use entry::{
+    frecency_cmp, is_excluded, load_entries, name_cmp, print_bin_preview, report_empty_sources,
+    sort_entries, Entry, OPTIONS,
+};
+use exec::{execute, execute_raw, is_launchable};
+use history::{clear_history, load_history, reset_count, save_last_query};
+use options::{build_options, get_max_results, DumpFormat, RESET_COUNT_ACTION};
+use stdin::run_stdin;
+use validate::validate_desktop_files;
+
+fn print_version_detailed() {
+    let features = env!("SKLAUNCHER_FEATURES");
+    println!("sklauncher {}", env!("CARGO_PKG_VERSION"));
+    println!("skim {}", env!("SKIM_VERSION"));
+    println!(
+        "features: {}",
+        if features.is_empty() {
+            "(none)"
+        } else {
+            features
+        }
+    );
+}
+
+// Entry written by `--dump`, with the resolved file of its icon
+#[derive(Serialize)]
+struct DumpedEntry<'a> {
+    #[serde(flatten)]
+    entry: &'a Entry,
+    icon_path: Option<PathBuf>,
+}
+
+// Write entries to stdout for `--dump`, stopping quietly when the reader goes away
+fn dump_entries(entries: &IndexMap<String, Entry>, format: DumpFormat) {
+    let mut out = BufWriter::new(io::stdout().lock());
+    for entry in entries.values() {
+        let line = match format {
+            DumpFormat::Jsonl => {
+                let dumped = DumpedEntry {
+                    entry,
+                    icon_path: entry.icon_path(),
+                };
+                serde_json::to_string(&dumped).expect("Failed to serialize entry")
+            }
+        };
+        if writeln!(out, "{}", line).is_err() {
+            return;
+        }
+    }
+    drop(out.flush());
+}
+
+// Check if the entry passes the filters of options: not hidden by `--exclude`, launchable
+// under `--allow` (a restricted launcher), able to open the clipboard content of
+// `--open-clipboard`, and declaring the interface of `--implements`
+fn is_shown(entry: &Entry) -> bool {
+    !is_excluded(entry)
+        && (OPTIONS.allow.is_empty() || is_launchable(entry))
+        && (!OPTIONS.open_clipboard || handles_clipboard(entry))
+        && OPTIONS
+            .implements
+            .as_ref()
+            .is_none_or(|iface| entry.implements(iface))
+}
+
+// Entries to show in the finder, filtered by options and sorted
+fn shown_entries(entries: &IndexMap<String, Entry>) -> IndexMap<String, Entry> {
+    let mut tmp_entries = entries.clone();
+    if OPTIONS.entries_only {
+        // the history of other runs is also loaded, show only listed entries
+        tmp_entries.retain(|_k, e| e.is_listed());
+    }
+    tmp_entries.retain(|_k, e| is_shown(e));
+    if OPTIONS.recent {
+        tmp_entries.retain(|_k, e| e.count > 0 && Path::new(&e.path).exists());
+        tmp_entries.sort_by(|_k1, v1, _k2, v2| {
+            frecency_cmp(v1, v2).then_with(|| name_cmp(v1, v2, &OPTIONS))
+        });
+    } else {
+        sort_entries(&mut tmp_entries, &OPTIONS);
+    }
+    tmp_entries.truncate(get_max_results());
+    tmp_entries
+}
+
+// Send entries to skim, returning the key of the top entry
+fn send_entries(entries: IndexMap<String, Entry>, tx_item: SkimItemSender) -> Option<String> {
+    let top_key = entries.keys().next().cloned();
+    for (_k, mut entry) in entries.into_iter() {
+        entry.init_matching_ranges();
+        drop(tx_item.send(Arc::new(entry)));
+    }
+    top_key
+}
 
-use entry::load_entries;
-use exec::{execute, execute_raw};
-use options::build_options;
+// Check if skim returned by `--reset-count-key`
+fn is_reset_count(output: &SkimOutput) -> bool {
+    matches!(&output.final_event, Event::EvActAccept(Some(action)) if action == RESET_COUNT_ACTION)
+}
 
 fn main() {
-    let mut entries = load_entries();
+    if let Some(path) = &OPTIONS.render_preview {
+        print_bin_preview(path);
+        return;
+    }
+
+    if OPTIONS.version_detailed {
+        print_version_detailed();
+        return;
+    }
+
+    if OPTIONS.validate {
+        let valid = validate_desktop_files();
+        std::process::exit(if valid { 0 } else { 1 });
+    }
+
+    if OPTIONS.clear_history {
+        let cleared = clear_history();
+        println!("Cleared {} history entries", cleared);
+        return;
+    }
+
     let options = build_options();
+    if OPTIONS.from_stdin {
+        run_stdin(&options);
+        return;
+    }
+
+    if OPTIONS.open_clipboard {
+        if let Err(err) = &*CLIPBOARD {
+            eprintln!("Cannot open the clipboard: {}", err);
+            std::process::exit(1);
+        }
+    }
 
     let (tx_item, rx_item): (SkimItemSender, SkimItemReceiver) = unbounded();
-    let mut tmp_entries = entries.clone();
-    tmp_entries.sort_by(|_k1, v1, _k2, v2| {
-        // sort entries by count
-        v2.count.cmp(&v1.count)
-    });
-    for (_k, entry) in tmp_entries.into_iter() {
-        drop(tx_item.send(Arc::new(entry)));
+    let mut entries;
+    let mut rescan = None;
+    if OPTIONS.recent {
+        // only previously used entries are shown, so no scan is needed
+        entries = load_history();
+    } else if OPTIONS.cache_first {
+        // serve entries cached in the history file, and rescan in background to update the
+        // cache and to add newly found entries to this session
+        entries = load_history();
+        let cached_keys: HashSet<String> = entries.keys().cloned().collect();
+        // new entries fill the room left by `--max-results`
+        let room = get_max_results().saturating_sub(shown_entries(&entries).len());
+        let tx_new = tx_item.clone();
+        rescan = Some(thread::spawn(move || {
+            let fresh = load_entries();
+            for (_key, entry) in fresh
+                .iter()
+                .filter(|(key, entry)| !cached_keys.contains(*key) && is_shown(entry))
+                .take(room)
+            {
+                let mut entry = entry.clone();
+                entry.init_matching_ranges();
+                drop(tx_new.send(Arc::new(entry)));
+            }
+            fresh
+        }));
+    } else {
+        entries = load_entries();
+        if entries.is_empty() {
+            report_empty_sources();
+            if OPTIONS.strict {
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let tmp_entries = shown_entries(&entries);
+    if let Some(format) = OPTIONS.dump {
+        dump_entries(&tmp_entries, format);
+        return;
     }
-    drop(tx_item);
+    let mut top_key = send_entries(tmp_entries, tx_item);
 
-    let output = Skim::run_with(&options, Some(rx_item));
+    let mut output = Skim::run_with(&options, Some(rx_item));
+    // reset the count of the entry by `--reset-count-key`, and run skim again with the same query
+    while let Some(prev) = output.take_if(|o| is_reset_count(o)) {
+        // wait for the rescan so that it doesn't overwrite the history
+        if let Some(rescan) = rescan.take() {
+            entries = rescan.join().expect("Failed to rescan entries");
+        }
+        if let Some(item) = prev.selected_items.first() {
+            reset_count(&item.output(), &mut entries);
+        }
+        let (tx_item, rx_item): (SkimItemSender, SkimItemReceiver) = unbounded();
+        top_key = send_entries(shown_entries(&entries), tx_item);
+        let mut options = build_options();
+        options.query = Some(&prev.query);
+        output = Skim::run_with(&options, Some(rx_item));
+    }
+    if let Some(output) = output.as_ref().filter(|_| OPTIONS.remember_query) {
+        save_last_query(&output.query);
+    }
+
+    // wait for the rescan so that the history file is not left half-written
+    if let Some(rescan) = rescan {
+        entries = rescan.join().expect("Failed to rescan entries");
+    }
 
     // error
     if output.is_none() {
@@ -41,10 +237,21 @@ fn main() {
     }
 
     // selected, execute command
-    if output.selected_items.is_empty() {
-        execute_raw(output.query);
+    let filestr = if !output.selected_items.is_empty() {
+        output.selected_items[0].output().to_string()
     } else {
-        let filestr = output.selected_items[0].output().to_string();
-        execute(filestr, &mut entries);
+        match top_key {
+            Some(key) if OPTIONS.accept_top && output.query.trim().is_empty() => key,
+            _ => {
+                execute_raw(output.query);
+                return;
+            }
+        }
+    };
+    if !entries.contains_key(&filestr) {
+        // cached entry which was removed since the previous run
+        eprintln!("Entry no longer exists: {}", filestr);
+        std::process::exit(1);
     }
+    execute(filestr, &mut entries);
 }
@@ -1,25 +1,358 @@
-use clap::{Parser, ValueEnum};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, ValueEnum};
+use regex::Regex;
+use skim::field::FieldRange;
 use skim::prelude::*;
 
 use crate::entry::OPTIONS;
+use crate::history::load_last_query;
+
+// Default key bindings, which can be overridden by `--bind`.
+// skim already binds shift-up/shift-down to scroll the preview by pages.
+const DEFAULT_BINDS: [&str; 2] = ["alt-up:preview-up", "alt-down:preview-down"];
+
+/// Argument of the `accept` action bound to `--reset-count-key`
+pub const RESET_COUNT_ACTION: &str = "reset-count";
+
+lazy_static! {
+    static ref PREVIEW_WINDOW: String = get_preview_window(&OPTIONS);
+    static ref NUMBER_BINDS: Vec<String> = get_number_binds(&OPTIONS);
+    static ref RESET_COUNT_BIND: Option<String> = get_reset_count_bind();
+    static ref COLOR: Option<String> = get_color(&OPTIONS);
+    static ref MIN_HEIGHT: String = get_min_height(&OPTIONS);
+    static ref QUERY: Option<String> = get_query();
+}
 
 #[derive(Parser)]
 #[command(name = "sklauncher")]
 #[command(version, about, author)]
 pub struct Cli {
+    /// Initial query. Words are joined with spaces, same as `--query`.
+    /// Ignored with `--from-stdin`.
+    #[arg(value_name = "QUERY")]
+    pub query_words: Vec<String>,
+
+    /// Initial query, taking precedence over the positional QUERY
+    #[arg(short, long, value_name = "QUERY")]
+    pub query: Option<String>,
+
+    /// Print the preview of the executable and exit. Used internally to render previews
+    /// without blocking the finder.
+    #[arg(long, hide = true, value_name = "PATH")]
+    pub render_preview: Option<String>,
+
+    /// Start with the last non-empty query of the previous run, which is saved next to the
+    /// history file. A query given by arguments takes precedence.
+    #[arg(long)]
+    pub remember_query: bool,
+
+    /// Print versions of sklauncher and skim, and enabled features
+    #[arg(long)]
+    pub version_detailed: bool,
+
+    /// Check all desktop files in the application directories against the desktop entry spec,
+    /// print problems found and exit. Exits with 1 if there are errors.
+    #[arg(long)]
+    pub validate: bool,
+
+    /// Clear the history (of `--profile` if given), including the query saved by
+    /// `--remember-query`, and exit
+    #[arg(long)]
+    pub clear_history: bool,
+
+    /// Write all loaded entries to stdout in the given format and exit, instead of selecting
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub dump: Option<DumpFormat>,
+
+    /// Select from lines read from stdin and print the selection instead of launching (dmenu mode)
+    #[arg(long)]
+    pub from_stdin: bool,
+
+    /// Number of spaces for a tab character in stdin lines. Only used with `--from-stdin`.
+    #[arg(long, default_value = "8", value_name = "N")]
+    pub tabstop: Option<String>,
+
+    /// Number of leading stdin lines shown as a fixed header instead of selectable items.
+    /// Only used with `--from-stdin`.
+    #[arg(long, default_value = "0", value_name = "N")]
+    pub header_lines: usize,
+
+    /// Show the Nth (from 1) field of the current line in the preview window.
+    /// Only used with `--from-stdin`.
+    #[arg(long, value_name = "N")]
+    pub preview_field: Option<NonZeroUsize>,
+
+    /// Field delimiter of stdin lines for `--preview-field` (default: tab)
+    #[arg(long, value_name = "STR")]
+    pub delimiter: Option<String>,
+
     /// Terminal launch command to be used for a desktop entry with Terminal=True.
     /// By default, `$TERM -e`. If `$TERM` is not defined, `alacritty -e`.
     #[arg(long, value_name = "COMMAND")]
     pub terminal_command: Option<String>,
 
+    /// Keep the terminal of a desktop entry with Terminal=True open after the command exits.
+    /// `flag` uses the hold option of known terminals (foot, alacritty, kitty, xterm, ...),
+    /// `shell` waits for Enter after the command, and `auto` (default) tries `flag` and falls
+    /// back to `shell`.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "STRATEGY",
+        num_args = 0..=1,
+        default_missing_value = "auto"
+    )]
+    pub terminal_hold: Option<TerminalHold>,
+
+    /// Window class (app-id on Wayland) of the terminal of a desktop entry with Terminal=True,
+    /// for window rules of window managers. Set by the class option of known terminals (foot,
+    /// alacritty, kitty, xterm, ...), and ignored for other terminals.
+    #[arg(long, value_name = "NAME")]
+    pub terminal_class: Option<String>,
+
+    /// File or URL to open with the selected desktop entry, passed by the %f/%F (as local paths)
+    /// and %u/%U (as URLs) field codes of its Exec key. Can be specified multiple times.
+    #[arg(long, value_name = "FILE|URL")]
+    pub open: Vec<String>,
+
+    /// Open the URL or file path in the clipboard, read by `wl-paste` on Wayland or `xclip`
+    /// otherwise. Only desktop entries handling its MIME type (`x-scheme-handler/SCHEME` for
+    /// URLs) are shown, and the content is passed to the selected entry like `--open`.
+    #[arg(long)]
+    pub open_clipboard: bool,
+
+    /// Show only desktop entries declaring the D-Bus interface IFACE in their `Implements` key,
+    /// e.g. `--implements org.freedesktop.FileManager1`
+    #[arg(long, value_name = "IFACE")]
+    pub implements: Option<String>,
+
+    /// Additional directory to search for executables, scanned after `$PATH`.
+    /// Can be specified multiple times.
+    #[arg(long, value_name = "PATH")]
+    pub bin_dir: Vec<PathBuf>,
+
+    /// Also search `~/.local/bin` for executables, after `--bin-dir`, even if it is not in
+    /// `$PATH`. Executables in directories out of `$PATH` are launched by absolute path.
+    #[arg(long)]
+    pub include_local_bin: bool,
+
+    /// TOML (`[[entry]]` tables) or JSON (array, `.json` extension) file of additional entries.
+    /// Each entry needs `name` and `exec`, and can have `generic_name`, `comment` and `terminal`.
+    /// `exec` is run like the Exec key of desktop entries.
+    #[arg(long, value_name = "PATH")]
+    pub entries_file: Option<PathBuf>,
+
+    /// Show only entries from `--entries-file`, without scanning directories
+    #[arg(long, requires = "entries_file")]
+    pub entries_only: bool,
+
+    /// Show only the desktop file in the directory of the highest precedence (the user directory
+    /// first) when files with the same ID exist in several application directories (default)
+    #[arg(long, overrides_with = "no_prefer_local")]
+    pub prefer_local: bool,
+
+    /// Show desktop files with the same ID from all application directories, so that both the
+    /// user's copy and the system file are listed
+    #[arg(long, overrides_with = "prefer_local")]
+    pub no_prefer_local: bool,
+
+    /// Scan only the top level of application directories, skipping subdirectories
+    #[arg(long)]
+    pub no_recursion: bool,
+
+    /// Use a separate history (`history-<NAME>.toml`) for the given profile name
+    #[arg(long, value_name = "NAME", value_parser = parse_profile)]
+    pub profile: Option<String>,
+
+    /// Show only previously launched entries, most frequently and recently used first.
+    /// Entries are read from the history without scanning directories.
+    #[arg(long)]
+    pub recent: bool,
+
+    /// Show entries cached by the previous run immediately, and rescan entries in background.
+    /// Newly found entries are added to the list when the rescan finishes.
+    #[arg(long)]
+    pub cache_first: bool,
+
+    /// Amount added to the launch count of an entry on each launch
+    #[arg(long, default_value = "1", value_name = "N")]
+    pub history_boost: u32,
+
+    /// Multiply launch counts in the history by FACTOR (0.0 to 1.0) per day, so that entries
+    /// not used for a long time fade. Applied when the history is saved, at most once a day.
+    #[arg(long, default_value = "1.0", value_name = "FACTOR", value_parser = parse_decay)]
+    pub history_decay: f64,
+
+    /// Order entries by a blend of launch count and recency instead of `--sort-by`. WEIGHT
+    /// (0.0 to 1.0) is the share of recency: 0.0 orders by count only, 1.0 by last use only.
+    #[arg(long, value_name = "WEIGHT", value_parser = parse_weight)]
+    pub history_weight: Option<f64>,
+
+    /// Exit with an error instead of opening an empty finder when no entries are found, and
+    /// report commands exiting with an error right after launch as failed launches
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Hide script extensions (.sh, .py, .pl, .rb) from names of executables
+    #[arg(long)]
+    pub strip_extension: bool,
+
+    /// Command prepended to every launched command, e.g. `systemd-run --user --scope`
+    #[arg(long, value_name = "PREFIX")]
+    pub command_prefix: Option<String>,
+
+    /// Evaluate the (prefixed) command with `SHELL -ic` so that aliases and functions of the
+    /// interactive shell can be used in `--command-prefix`. The shell reads its rc files on every
+    /// launch, which is slower, and anything they run or print happens on each launch too.
+    #[arg(long, value_name = "SHELL")]
+    pub prefix_shell: Option<String>,
+
+    /// Run commands in a login shell (`sh -lc`) so that profile scripts like `~/.profile` are
+    /// sourced. This makes every launch slower by the time the profile scripts take.
+    #[arg(long)]
+    pub login_shell: bool,
+
+    /// Launch desktop entries with the login environment of `$SHELL`, which is captured once
+    /// by `$SHELL -lc env`. Apps see the variables set in profile scripts as if launched from
+    /// the desktop session. Executables keep the environment of sklauncher.
+    #[arg(long)]
+    pub session_env: bool,
+
+    /// Run launched commands on the discrete GPU by PRIME render offload
+    #[arg(long)]
+    pub prime: bool,
+
+    /// Run desktop entries with `PrefersNonDefaultGPU=true` on the discrete GPU
+    #[arg(long)]
+    pub prime_auto: bool,
+
+    /// Driver of the discrete GPU, which determines the variables set by `--prime` and
+    /// `--prime-auto`
+    #[arg(long, value_enum, default_value = "mesa", value_name = "VENDOR")]
+    pub prime_vendor: Option<PrimeVendor>,
+
+    /// Do not use `setsid` to detach launched commands. Commands are started in a new process
+    /// group instead. This is also the fallback when `setsid` is not found in `$PATH`.
+    #[arg(long)]
+    pub no_setsid: bool,
+
+    /// Launch desktop entries by their desktop ID with `gtk-launch`, so that the app gets its
+    /// identity on Wayland compositors. `auto` (default) uses it only on Wayland when `gtk-launch`
+    /// is installed, and `always` everywhere. The desktop file is run as installed, so
+    /// `X-Sklauncher-Exec`, override files and terminal options don't apply. Actions and
+    /// `--wait` use `Exec`, as does an entry which `gtk-launch` fails to launch.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "WHEN",
+        num_args = 0..=1,
+        default_missing_value = "auto"
+    )]
+    pub use_gtk_launch: Option<GtkLaunch>,
+
+    /// Command spawned to launch commands instead of `setsid sh -c {cmd}`. The template is split
+    /// into words like the shell does, and `{cmd}` in each word is replaced with the shell
+    /// command to launch, e.g. `--exec-template 'systemd-run --user --scope sh -c {cmd}'`.
+    /// `--login-shell` and `--no-setsid` have no effect with a template.
+    #[arg(long, value_name = "TEMPLATE", value_parser = parse_exec_template)]
+    pub exec_template: Option<String>,
+
+    /// Hide entries whose name or desktop ID matches the glob PATTERN, ignoring case, e.g.
+    /// `--exclude 'org.gnome.*'`. Can be specified multiple times. Patterns are also read from
+    /// `sklauncher/exclude` in the config directories, one per line.
+    #[arg(long, value_name = "PATTERN", value_parser = parse_glob)]
+    pub exclude: Vec<Regex>,
+
+    /// Refuse to launch commands whose program name (basename) fully matches the regex PATTERN,
+    /// e.g. `--deny 'rm|dd|shutdown'`. Can be specified multiple times. `NAME=value` words and
+    /// `env` before the program are skipped, and commands run by other wrappers like `sudo` or
+    /// `sh -c` are refused. Queries launched as commands are refused if they contain shell
+    /// operators like `;`, `|` or `$(`.
+    #[arg(long, value_name = "PATTERN", value_parser = parse_name_pattern)]
+    pub deny: Vec<Regex>,
+
+    /// Show and launch only commands whose program name (basename) fully matches the regex
+    /// PATTERN. Can be specified multiple times. `--deny` takes precedence. The program is found,
+    /// and commands of wrappers and queries with shell operators are refused, as with `--deny`.
+    #[arg(long, value_name = "PATTERN", value_parser = parse_name_pattern)]
+    pub allow: Vec<Regex>,
+
+    /// Check that the program of the selected entry still exists before launching it,
+    /// and exit with an error if it doesn't
+    #[arg(long)]
+    pub require_exec_resolves: bool,
+
+    /// Load only desktop entries whose group header is spelled exactly `[Desktop Entry]` as the
+    /// spec requires. By default, headers differing in case or surrounding whitespace, like
+    /// `[desktop entry]`, are also accepted.
+    #[arg(long)]
+    pub strict_desktop: bool,
+
+    /// Launch desktop entries without `Exec` by running the program of `TryExec` if it is
+    /// installed. Such entries are skipped by default as the spec requires `Exec`.
+    #[arg(long)]
+    pub exec_fallback_tryexec: bool,
+
+    /// Print how the selected entry would be launched to stderr instead of launching it.
+    /// History is not updated.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Count a launch in the history only if the command was started successfully, and with
+    /// `--wait`, if it exited with status 0. Otherwise failed launches also raise the rank.
+    /// Commands exiting with an error right after launch are regarded as failed, like `--strict`.
+    #[arg(long)]
+    pub launch_count_increment_only_on_success: bool,
+
+    /// Run the launched command in the foreground instead of detaching it, wait until it
+    /// exits, and exit with its status. Terminal apps are waited while the terminal window is
+    /// open if the terminal program stays in the foreground.
+    #[arg(long)]
+    pub wait: bool,
+
     /// Show GenericName field of desktop entries
     #[arg(long)]
     pub show_generic_name: bool,
 
+    /// Separator between Name and GenericName fields when both are shown or matched
+    #[arg(long, default_value = ", ", value_name = "STR")]
+    pub generic_separator: Option<String>,
+
+    /// Template of the label shown for each entry, overriding `--show-generic-name`.
+    /// Available placeholders are `{name}`, `{generic}` and `{comment}`.
+    /// Example: --label-format "{name} — {generic}"
+    #[arg(long, value_name = "TEMPLATE", verbatim_doc_comment)]
+    pub label_format: Option<String>,
+
+    /// Show all entries alike without the icon telling desktop entries from executables
+    #[arg(long)]
+    pub flatten: bool,
+
+    /// Show desktop file ID (or path of executables) dimmed after each entry
+    #[arg(long)]
+    pub show_id: bool,
+
+    /// Show launch count dimmed after each used entry, e.g. `×42`
+    #[arg(long)]
+    pub show_count: bool,
+
     /// Include GenericName field of desktop entries to match string
     #[arg(long)]
     pub match_generic_name: bool,
 
+    /// Also match queries against GenericName, Comment, Keywords and Categories of desktop
+    /// entries. Each of them is matched separately, and only the label is displayed.
+    #[arg(long)]
+    pub match_all: bool,
+
+    /// Match queries only against the given words (separated by spaces) of names, e.g. `1` for
+    /// the first word or `2..` for all but the first. Comma-separated list of field ranges.
+    #[arg(long, value_delimiter = ',', value_parser = parse_field_range, value_name = "FIELDS")]
+    pub match_nth: Vec<FieldRange>,
+
     /// Fuzzy Matching algorithm
     #[arg(long, default_value = "skim-v2", value_name = "ALGORITHM")]
     pub algorithm: Option<Algorithm>,
@@ -31,10 +364,47 @@ pub struct Cli {
         short,
         long,
         value_enum,
+        value_delimiter = ',',
         default_value = "score",
         value_name = "CRITERIA"
     )]
-    pub tiebreak: Option<Tiebreak>,
+    pub tiebreak: Vec<Tiebreak>,
+
+    /// Initial order of entries before any query is typed
+    #[arg(long, value_enum, default_value = "count", value_name = "CRITERION")]
+    pub sort_by: Option<SortBy>,
+
+    /// Place all desktop entries before executables in the initial order, each group sorted by
+    /// `--sort-by`. Use with `--no-sort` to keep the grouping while typing a query.
+    #[arg(long)]
+    pub group: bool,
+
+    /// Keep entries tied by `--sort-by` in the order they are loaded instead of sorting them by
+    /// name
+    #[arg(long)]
+    pub no_name_tiebreak: bool,
+
+    /// Sort names case-sensitively, putting uppercase letters before lowercase ones. By default,
+    /// names are sorted ignoring case.
+    #[arg(long)]
+    pub case_sensitive_sort: bool,
+
+    /// Launch the Nth entry from the top of the list by pressing 1-9.
+    /// Digits can't be typed in the query with this option. The entries are not numbered in the
+    /// list, as skim doesn't tell the position of an entry when displaying it.
+    #[arg(long)]
+    pub numbered: bool,
+
+    /// Launch the first entry (the most used one by default) when accepted with an empty query
+    /// and nothing selected, instead of running the empty query as a command
+    #[arg(long)]
+    pub accept_top: bool,
+
+    /// Maximum number of entries passed to the finder, keeping the top entries by `--sort-by`.
+    /// New entries found by the rescan of `--cache-first` are added up to the limit, and only
+    /// the first N lines are read with `--from-stdin`. 0 means unlimited.
+    #[arg(long, value_name = "N")]
+    pub max_results: Option<usize>,
 
     /// Do not sort the search result
     #[arg(long)]
@@ -44,6 +414,11 @@ pub struct Cli {
     #[arg(short, long)]
     pub exact: bool,
 
+    /// Disable extended search syntax (`'exact`, `^prefix`, `suffix$`, `!negate` and `a | b`)
+    /// so that the whole query is matched literally
+    #[arg(long)]
+    pub no_extended: bool,
+
     /// Enable regex-mode
     #[arg(long)]
     pub regex: bool,
@@ -87,14 +462,15 @@ pub struct Cli {
     ///     marker|selected   Multi-select marker (no effect now)
     ///     spinner           Streaming input indicator (no effect now)
     ///     header            Header (no effect now)
-    #[arg(
-        long,
-        default_value = "default",
-        value_name = "COLOR",
-        verbatim_doc_comment
-    )]
+    ///
+    /// Mappings are applied on top of `--theme` if given.
+    #[arg(long, value_name = "COLOR", verbatim_doc_comment)]
     pub color: Option<String>,
 
+    /// Use a preset color theme
+    #[arg(long, value_enum, value_name = "THEME")]
+    pub theme: Option<Theme>,
+
     /// Choose the layout
     #[arg(long, value_enum, default_value = "default", value_name = "LAYOUT")]
     pub layout: Option<Layout>,
@@ -108,8 +484,8 @@ pub struct Cli {
     pub height: Option<String>,
 
     /// Minimum height when `--height` is given in percent (default: 10).
-    /// Ignored when `--height` is not specified.
-    #[arg(long, default_value = "10", value_name = "HEIGHT")]
+    /// Ignored when `--height` is an absolute number of lines.
+    #[arg(long, value_name = "HEIGHT")]
     pub min_height: Option<String>,
 
     /// Screen margin (TRBL / TB,RL / T,RL,B / T,R,B,L)
@@ -121,25 +497,251 @@ pub struct Cli {
     #[arg(short, long, default_value = "> ")]
     pub prompt: Option<String>,
 
-    /// Display info next to query
+    /// Where to display the info line (match counter). `hidden` is the same as `inline`, as
+    /// skim 0.10.4 always displays the info.
+    #[arg(long, value_enum, default_value = "default", value_name = "STYLE")]
+    pub info: Option<InfoStyle>,
+
+    /// Display info next to query (deprecated alias for `--info=inline`)
     #[arg(long)]
     pub inline_info: bool,
 
+    /// Key which resets the launch count of the entry under the cursor to zero and reloads the
+    /// list, e.g. `--reset-count-key ctrl-x`. Keys are named as in `--bind`.
+    #[arg(long, value_name = "KEY")]
+    pub reset_count_key: Option<String>,
+
+    /// Custom key bindings: KEY:ACTION[,KEY:ACTION]... Can be specified multiple times.
+    /// By default, alt-up and alt-down scroll the preview window by a line.
+    /// See skim's document for available keys and actions.
+    #[arg(long, value_name = "KEYBINDS")]
+    pub bind: Vec<String>,
+
     /// Disable preview window
     #[arg(long)]
     pub no_preview: bool,
 
-    /// Preview window layout
+    /// Preview window layout (default: right:50%). Overrides `--preview-position` and
+    /// `--preview-size`.
     ///
     /// format: [up|down|left|right][:SIZE[%]][:hidden][:SCROLL[-OFFSET]]
-    #[arg(long, default_value = "right:50%", value_name = "PREVIEW")]
+    #[arg(long, value_name = "PREVIEW")]
     pub preview_window: Option<String>,
 
+    /// Show only the name and metadata in the preview window, without comments of desktop
+    /// entries and descriptions of executables. No command is run for previews.
+    #[arg(long)]
+    pub no_comment_preview: bool,
+
+    /// Side of the preview window, keeping the default size
+    #[arg(long, value_enum, value_name = "SIDE")]
+    pub preview_position: Option<PreviewPosition>,
+
+    /// Size of the preview window in lines/columns, or in percent of the screen with `%`,
+    /// keeping the default side (default: 50%)
+    #[arg(long, value_parser = parse_preview_size, value_name = "SIZE[%]")]
+    pub preview_size: Option<String>,
+
+    /// Wrap long lines in the preview window (default)
+    #[arg(long, overrides_with = "no_preview_wrap")]
+    pub preview_wrap: bool,
+
+    /// Truncate long lines in the preview window instead of wrapping them, even if `wrap` is
+    /// given in `--preview-window`
+    #[arg(long, overrides_with = "preview_wrap")]
+    pub no_preview_wrap: bool,
+
+    /// Comma-separated list of sources tried in order for the preview of executables.
+    /// `help` runs the command itself with `--help`, so it is not enabled by default.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "whatis,tldr",
+        value_name = "SOURCES"
+    )]
+    pub bin_preview: Vec<BinPreview>,
+
+    /// Shell command whose output is shown in the preview of executables instead of the built-in
+    /// preview. `{}` is replaced with the quoted path and `{name}` with the file name, e.g.
+    /// `--bin-preview-command 'tldr {name}'`.
+    #[arg(long, value_name = "COMMAND")]
+    pub bin_preview_command: Option<String>,
+
+    /// Shell command whose output is shown in the preview of desktop entries instead of the
+    /// built-in preview. `{}` is replaced with the quoted path of the desktop file and `{name}`
+    /// with its file name, e.g. `--desktop-preview-command 'cat {}'`.
+    #[arg(long, value_name = "COMMAND")]
+    pub desktop_preview_command: Option<String>,
+
+    /// Time limit in milliseconds of commands run to build the preview
+    #[arg(long, default_value = "500", value_name = "MS")]
+    pub preview_timeout: u64,
+
+    /// Colors of the preview window segments: [SEGMENT:COLOR][,SEGMENT:COLOR]...
+    ///
+    /// SEGMENT is one of `name`, `generic`, `comment` and `meta`.
+    /// COLOR is a color name (black, red, green, yellow, blue, magenta, cyan, white),
+    /// an ANSI 256-color code, or a 24-bit color in #rrggbb format.
+    /// `name` defaults to `--accent-color` and `meta` to dimmed text.
+    ///
+    /// Example: --preview-theme name:#88c0d0,comment:245
+    #[arg(long, value_parser = parse_preview_theme, value_name = "THEME", verbatim_doc_comment)]
+    pub preview_theme: Option<PreviewTheme>,
+
+    /// Icon theme searched first for icons of desktop entries, followed by the themes it
+    /// inherits from and `hicolor` (default: the icon theme of GTK or KDE settings)
+    #[arg(long, value_name = "NAME")]
+    pub icon_theme: Option<String>,
+
     /// Accent color used in preview window
     #[arg(long, value_enum, default_value = "magenta", value_name = "COLOR")]
     pub accent_color: Option<AccentColor>,
 }
 
+fn parse_profile(name: &str) -> Result<String, String> {
+    if name.is_empty() || name.contains(std::path::is_separator) {
+        Err("profile name must be non-empty and must not contain path separators".to_string())
+    } else {
+        Ok(name.to_string())
+    }
+}
+
+// Compile a regex matching whole program names
+fn parse_name_pattern(value: &str) -> Result<Regex, String> {
+    Regex::new(&format!("^(?:{})$", value)).map_err(|e| e.to_string())
+}
+
+/// Convert a glob pattern into a case-insensitive regex matching the whole string. `*` and `?`
+/// match any characters and `[...]` (or `[!...]`) a set of characters.
+pub fn parse_glob(value: &str) -> Result<Regex, String> {
+    let mut pattern = String::from("(?i)^");
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '[' => {
+                let mut class = String::new();
+                if chars.next_if(|&c| c == '!' || c == '^').is_some() {
+                    class.push('^');
+                }
+                loop {
+                    match chars.next() {
+                        Some(']') if class.trim_start_matches('^').is_empty() => {
+                            class.push_str("\\]")
+                        }
+                        Some(']') => break,
+                        Some(c) if c == '\\' || c == '[' => {
+                            class.push('\\');
+                            class.push(c);
+                        }
+                        Some(c) => class.push(c),
+                        None => return Err("unclosed character class".to_string()),
+                    }
+                }
+                pattern.push('[');
+                pattern.push_str(&class);
+                pattern.push(']');
+            }
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern).map_err(|e| e.to_string())
+}
+
+fn parse_exec_template(value: &str) -> Result<String, String> {
+    match shlex::split(value) {
+        Some(words) if words.iter().any(|word| word.contains("{cmd}")) => Ok(value.to_string()),
+        Some(_) => Err("template must contain {cmd}".to_string()),
+        None => Err("template has unbalanced quotes".to_string()),
+    }
+}
+
+fn parse_preview_size(value: &str) -> Result<String, String> {
+    let (number, percent) = match value.strip_suffix('%') {
+        Some(number) => (number, true),
+        None => (value, false),
+    };
+    match number.parse::<u16>() {
+        Ok(size) if percent && size > 100 => Err("size must be at most 100%".to_string()),
+        Ok(_) => Ok(value.to_string()),
+        Err(_) => Err("expected a number of lines/columns or a percentage like 40%".to_string()),
+    }
+}
+
+fn parse_field_range(value: &str) -> Result<FieldRange, String> {
+    FieldRange::from_str(value).ok_or_else(|| format!("invalid field range: {}", value))
+}
+
+fn parse_weight(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(weight) if (0.0..=1.0).contains(&weight) => Ok(weight),
+        _ => Err("weight must be a number from 0.0 to 1.0".to_string()),
+    }
+}
+
+fn parse_decay(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(factor) if (0.0..=1.0).contains(&factor) => Ok(factor),
+        _ => Err("decay factor must be a number from 0.0 to 1.0".to_string()),
+    }
+}
+
+/// SGR parameters of the preview window segments
+#[derive(Clone, Default)]
+pub struct PreviewTheme {
+    pub name: Option<String>,
+    pub generic: Option<String>,
+    pub comment: Option<String>,
+    pub meta: Option<String>,
+}
+
+// Convert color to SGR parameters for the foreground
+fn parse_color(color: &str) -> Result<String, String> {
+    let names = [
+        "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+    ];
+    if let Some(index) = names.iter().position(|&n| n == color) {
+        return Ok(format!("3{}", index));
+    }
+    if let Ok(code) = color.parse::<u8>() {
+        return Ok(format!("38;5;{}", code));
+    }
+    if let Some(hex) = color.strip_prefix('#') {
+        if hex.len() == 6 {
+            if let Ok(rgb) = u32::from_str_radix(hex, 16) {
+                return Ok(format!(
+                    "38;2;{};{};{}",
+                    rgb >> 16,
+                    (rgb >> 8) & 0xff,
+                    rgb & 0xff
+                ));
+            }
+        }
+    }
+    Err(format!("invalid color: {}", color))
+}
+
+fn parse_preview_theme(value: &str) -> Result<PreviewTheme, String> {
+    let mut theme = PreviewTheme::default();
+    for spec in value.split(',').filter(|s| !s.is_empty()) {
+        let (segment, color) = spec
+            .split_once(':')
+            .ok_or_else(|| format!("expected SEGMENT:COLOR, got '{}'", spec))?;
+        let sgr = Some(parse_color(color)?);
+        match segment {
+            "name" => theme.name = sgr,
+            "generic" => theme.generic = sgr,
+            "comment" => theme.comment = sgr,
+            "meta" => theme.meta = sgr,
+            _ => return Err(format!("unknown preview segment: {}", segment)),
+        }
+    }
+    Ok(theme)
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum Algorithm {
     /// Skim's legacy algorithm
@@ -151,11 +753,46 @@ pub enum Algorithm {
 }
 
 impl Algorithm {
-    pub fn as_str(&self) -> &str {
+    // Map directly to skim's algorithm instead of `FuzzyAlgorithm::of`,
+    // which silently falls back to the default on unknown names
+    pub fn fuzzy_algorithm(&self) -> FuzzyAlgorithm {
         match self {
-            Algorithm::SkimV1 => "skim_v1",
-            Algorithm::SkimV2 => "skim_v2",
-            Algorithm::Clangd => "clangd",
+            Algorithm::SkimV1 => FuzzyAlgorithm::SkimV1,
+            Algorithm::SkimV2 => FuzzyAlgorithm::SkimV2,
+            Algorithm::Clangd => FuzzyAlgorithm::Clangd,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Theme {
+    /// Gruvbox dark
+    Gruvbox,
+    /// Nord
+    Nord,
+    /// Solarized dark
+    Solarized,
+}
+
+impl Theme {
+    // Expand to the `--color` string of the preset
+    pub fn color(&self) -> &str {
+        match self {
+            Theme::Gruvbox => concat!(
+                "dark,fg:#ebdbb2,bg:#282828,hl:#fabd2f,fg+:#ebdbb2,bg+:#3c3836,hl+:#fabd2f,",
+                "query:#ebdbb2,info:#83a598,border:#665c54,prompt:#bdae93,pointer:#fb4934,",
+                "marker:#fe8019,spinner:#8ec07c,header:#83a598"
+            ),
+            Theme::Nord => concat!(
+                "dark,fg:#d8dee9,bg:#2e3440,hl:#88c0d0,fg+:#eceff4,bg+:#3b4252,hl+:#8fbcbb,",
+                "query:#eceff4,info:#81a1c1,border:#4c566a,prompt:#81a1c1,pointer:#bf616a,",
+                "marker:#ebcb8b,spinner:#b48ead,header:#5e81ac"
+            ),
+            Theme::Solarized => concat!(
+                "dark,fg:#839496,bg:#002b36,hl:#268bd2,fg+:#93a1a1,bg+:#073642,hl+:#2aa198,",
+                "query:#93a1a1,info:#b58900,border:#586e75,prompt:#b58900,pointer:#dc322f,",
+                "marker:#d33682,spinner:#6c71c4,header:#586e75"
+            ),
         }
     }
 }
@@ -173,16 +810,68 @@ pub enum Tiebreak {
 }
 
 impl Tiebreak {
-    pub fn to_string(&self) -> String {
+    pub fn as_str(&self) -> &str {
         match self {
-            Tiebreak::Score => "score".to_string(),
-            Tiebreak::Index => "index".to_string(),
-            Tiebreak::Begin => "begin".to_string(),
-            Tiebreak::End => "end".to_string(),
+            Tiebreak::Score => "score",
+            Tiebreak::Index => "index",
+            Tiebreak::Begin => "begin",
+            Tiebreak::End => "end",
         }
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum SortBy {
+    /// Launch count, most used first (default)
+    Count,
+    /// Launch count weighted by how recently the entry was used
+    Frecency,
+    /// Name only, ignoring history
+    Name,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum BinPreview {
+    /// One-line description from man pages
+    Whatis,
+    /// Examples from tldr pages
+    Tldr,
+    /// Output of `COMMAND --help`
+    Help,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum TerminalHold {
+    /// Hold option of the terminal if known, otherwise `shell`
+    Auto,
+    /// Hold option of the terminal only
+    Flag,
+    /// Wait for Enter in a shell after the command
+    Shell,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum GtkLaunch {
+    /// Only on Wayland when `gtk-launch` is installed
+    Auto,
+    /// For all desktop entries
+    Always,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum PrimeVendor {
+    /// Mesa drivers (`DRI_PRIME=1`)
+    Mesa,
+    /// NVIDIA proprietary driver (`__NV_PRIME_RENDER_OFFLOAD=1`)
+    Nvidia,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum DumpFormat {
+    /// One JSON object per line
+    Jsonl,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum Layout {
     Default,
@@ -190,6 +879,24 @@ pub enum Layout {
     ReverseList,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum PreviewPosition {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum InfoStyle {
+    /// Display info on its own line below the query
+    Default,
+    /// Display info next to the query
+    Inline,
+    /// Same as `inline`: skim 0.10.4 cannot hide the info, so it is kept off its own line
+    Hidden,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum AccentColor {
     Black,
@@ -202,21 +909,280 @@ pub enum AccentColor {
     White,
 }
 
+// Join `--tiebreak` criteria for skim, or fail if any criterion is duplicated
+fn join_tiebreak(criteria: &[Tiebreak]) -> Result<String, String> {
+    for (i, criterion) in criteria.iter().enumerate() {
+        if criteria[..i].contains(criterion) {
+            return Err(format!(
+                "tiebreak criterion '{}' appears more than once",
+                criterion.as_str()
+            ));
+        }
+    }
+    Ok(criteria
+        .iter()
+        .map(Tiebreak::as_str)
+        .collect::<Vec<&str>>()
+        .join(","))
+}
+
+// Join `--tiebreak` criteria, exiting with an error if any criterion is duplicated
+fn get_tiebreak() -> String {
+    join_tiebreak(&OPTIONS.tiebreak)
+        .unwrap_or_else(|err| Cli::command().error(ErrorKind::ValueValidation, err).exit())
+}
+
+// Ranks of matched items by `--tiebreak` criteria, like skim's rank builder which is private
+// to skim. `index` is left to skim, which keeps the input order of items of equal rank.
+struct RankBuilder {
+    criteria: Vec<Tiebreak>,
+}
+
+impl RankBuilder {
+    fn new(criteria: &[Tiebreak]) -> Self {
+        let mut criteria: Vec<Tiebreak> = criteria
+            .iter()
+            .copied()
+            .filter(|criterion| *criterion != Tiebreak::Index)
+            .collect();
+        if !criteria.contains(&Tiebreak::Score) {
+            criteria.insert(0, Tiebreak::Score);
+        }
+        Self { criteria }
+    }
+
+    fn build_rank(&self, score: i32, begin: i32, end: i32) -> Rank {
+        let mut rank = [0; 4];
+        for (index, criterion) in self.criteria.iter().take(4).enumerate() {
+            rank[index] = match criterion {
+                Tiebreak::Score => -score,
+                Tiebreak::Begin => begin,
+                Tiebreak::End => end,
+                Tiebreak::Index => 0,
+            };
+        }
+        rank
+    }
+}
+
+// Engine re-ranking matches of an engine built with skim's default criteria
+// (score, begin, end) by `--tiebreak`
+struct RankedEngine {
+    engine: Box<dyn MatchEngine>,
+    rank_builder: Arc<RankBuilder>,
+}
+
+impl MatchEngine for RankedEngine {
+    fn match_item(&self, item: Arc<dyn SkimItem>) -> Option<MatchResult> {
+        let mut result = self.engine.match_item(item)?;
+        let [score, begin, end, _] = result.rank;
+        result.rank = self.rank_builder.build_rank(-score, begin, end);
+        Some(result)
+    }
+}
+
+impl std::fmt::Display for RankedEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.engine.fmt(f)
+    }
+}
+
+// Matcher used for `--no-extended`, which passes the whole query to a single engine
+// instead of parsing the operators of skim's extended search syntax
+struct LiteralEngineFactory {
+    exact: bool,
+    fuzzy: ExactOrFuzzyEngineFactory,
+    regex: RegexEngineFactory,
+    rank_builder: Arc<RankBuilder>,
+}
+
+impl LiteralEngineFactory {
+    fn new(exact: bool, algorithm: FuzzyAlgorithm, rank_builder: Arc<RankBuilder>) -> Self {
+        Self {
+            exact,
+            rank_builder,
+            // in exact mode, a query starting with `'` is passed to the fuzzy engine as is
+            fuzzy: ExactOrFuzzyEngineFactory::builder()
+                .exact_mode(true)
+                .fuzzy_algorithm(algorithm)
+                .build(),
+            regex: RegexEngineFactory::builder().build(),
+        }
+    }
+}
+
+impl MatchEngineFactory for LiteralEngineFactory {
+    fn create_engine_with_case(&self, query: &str, case: CaseMatching) -> Box<dyn MatchEngine> {
+        let engine = if self.exact {
+            // the regex engine respects case for smart case, so resolve it here
+            let case = match case {
+                CaseMatching::Smart if !query.chars().any(char::is_uppercase) => {
+                    CaseMatching::Ignore
+                }
+                _ => case,
+            };
+            self.regex
+                .create_engine_with_case(&regex::escape(query), case)
+        } else {
+            self.fuzzy
+                .create_engine_with_case(&format!("'{}", query), case)
+        };
+        Box::new(RankedEngine {
+            engine,
+            rank_builder: self.rank_builder.clone(),
+        })
+    }
+}
+
+fn get_engine_factory() -> Option<Rc<dyn MatchEngineFactory>> {
+    if !OPTIONS.no_extended {
+        return None;
+    }
+    let algorithm = OPTIONS
+        .algorithm
+        .unwrap_or(Algorithm::SkimV2)
+        .fuzzy_algorithm();
+    let rank_builder = Arc::new(RankBuilder::new(&OPTIONS.tiebreak));
+    Some(Rc::new(LiteralEngineFactory::new(
+        OPTIONS.exact,
+        algorithm,
+        rank_builder,
+    )))
+}
+
+// Key bindings of `--numbered`, which move the cursor to the first entry and then to the Nth
+// entry before accepting. Cursor movement is inverted in the reverse layouts.
+fn get_number_binds(options: &Cli) -> Vec<String> {
+    if !options.numbered {
+        return Vec::new();
+    }
+    let reverse = options.reverse || options.layout.unwrap_or(Layout::Default) != Layout::Default;
+    let (to_first, to_next) = if reverse {
+        ("up", "down")
+    } else {
+        ("down", "up")
+    };
+    (1..=9)
+        .map(|n| format!("{}:{}(9999)+{}({})+accept", n, to_first, to_next, n - 1))
+        .collect()
+}
+
+// Key binding of `--reset-count-key`, which makes skim return with `RESET_COUNT_ACTION`
+fn get_reset_count_bind() -> Option<String> {
+    let key = OPTIONS
+        .reset_count_key
+        .as_ref()
+        .filter(|_| !OPTIONS.from_stdin)?;
+    Some(format!("{}:accept({})", key, RESET_COUNT_ACTION))
+}
+
+// Colors of `--theme` followed by `--color`, so that explicit mappings take precedence
+fn get_color(options: &Cli) -> Option<String> {
+    match (options.theme, &options.color) {
+        (Some(theme), Some(color)) => Some(format!("{},{}", theme.color(), color)),
+        (Some(theme), None) => Some(theme.color().to_string()),
+        (None, color) => color.clone(),
+    }
+}
+
+fn get_query() -> Option<String> {
+    if OPTIONS.query.is_some() || OPTIONS.from_stdin {
+        return OPTIONS.query.clone();
+    }
+    if !OPTIONS.query_words.is_empty() {
+        return Some(OPTIONS.query_words.join(" "));
+    }
+    if OPTIONS.remember_query {
+        return load_last_query();
+    }
+    None
+}
+
+// Minimum height passed to skim, which applies it to absolute heights too.
+// It is only meaningful for heights in percent, so a given `--min-height` is ignored with a
+// warning otherwise, and the absolute height is kept as is.
+fn get_min_height(options: &Cli) -> String {
+    let percent = options.height.as_deref().is_none_or(|h| h.ends_with('%'));
+    match &options.min_height {
+        Some(min_height) if percent => min_height.clone(),
+        None if percent => "10".to_string(),
+        Some(_) => {
+            eprintln!("--min-height is ignored because --height is not given in percent");
+            "0".to_string()
+        }
+        None => "0".to_string(),
+    }
+}
+
+/// Maximum number of items passed to the finder by `--max-results`
+pub fn get_max_results() -> usize {
+    match OPTIONS.max_results {
+        Some(0) | None => usize::MAX,
+        Some(max) => max,
+    }
+}
+
+fn get_preview_window(options: &Cli) -> String {
+    let mut preview_window = options.preview_window.clone().unwrap_or_else(|| {
+        let side = match options.preview_position.unwrap_or(PreviewPosition::Right) {
+            PreviewPosition::Top => "up",
+            PreviewPosition::Bottom => "down",
+            PreviewPosition::Left => "left",
+            PreviewPosition::Right => "right",
+        };
+        let size = options.preview_size.as_deref().unwrap_or("50%");
+        format!("{}:{}", side, size)
+    });
+    if options.no_preview_wrap {
+        // drop `wrap` given in `--preview-window`
+        preview_window = preview_window
+            .split(':')
+            .filter(|t| *t != "wrap")
+            .collect::<Vec<&str>>()
+            .join(":");
+    } else if !preview_window.split(':').any(|t| t == "wrap") {
+        preview_window.push_str(":wrap");
+    }
+    preview_window
+}
+
+// Whether the info is displayed next to the query by `--info` or `--inline-info`
+fn is_info_inline(options: &Cli) -> bool {
+    options.inline_info || options.info.unwrap_or(InfoStyle::Default) != InfoStyle::Default
+}
+
 pub fn build_options() -> SkimOptions<'static> {
     SkimOptionsBuilder::default()
         .multi(false)
-        .preview(if OPTIONS.no_preview { None } else { Some("") })
-        .algorithm(FuzzyAlgorithm::of(
-            OPTIONS.algorithm.unwrap_or(Algorithm::SkimV2).as_str(),
-        ))
-        .tiebreak(Some(
-            OPTIONS.tiebreak.unwrap_or(Tiebreak::Score).to_string(),
-        ))
+        .preview(
+            if OPTIONS.no_preview || (OPTIONS.from_stdin && OPTIONS.preview_field.is_none()) {
+                None
+            } else {
+                Some("")
+            },
+        )
+        .algorithm(
+            OPTIONS
+                .algorithm
+                .unwrap_or(Algorithm::SkimV2)
+                .fuzzy_algorithm(),
+        )
+        .tiebreak(Some(get_tiebreak()))
         .nosort(OPTIONS.no_sort)
         .exact(OPTIONS.exact)
         .regex(OPTIONS.regex)
-        .color(OPTIONS.color.as_deref())
-        .preview_window(OPTIONS.preview_window.as_deref())
+        .engine_factory(get_engine_factory())
+        .bind(
+            DEFAULT_BINDS
+                .iter()
+                .copied()
+                .chain(NUMBER_BINDS.iter().map(String::as_str))
+                .chain(RESET_COUNT_BIND.iter().map(String::as_str))
+                .chain(OPTIONS.bind.iter().map(String::as_str))
+                .collect(),
+        )
+        .color(COLOR.as_deref())
+        .preview_window(Some(&PREVIEW_WINDOW))
         .layout(if OPTIONS.reverse {
             "reverse"
         } else {
@@ -227,10 +1193,219 @@ pub fn build_options() -> SkimOptions<'static> {
             }
         })
         .height(OPTIONS.height.as_deref())
-        .min_height(OPTIONS.min_height.as_deref())
+        .min_height(Some(&MIN_HEIGHT))
         .margin(OPTIONS.margin.as_deref())
         .prompt(OPTIONS.prompt.as_deref())
-        .inline_info(OPTIONS.inline_info)
+        .query(QUERY.as_deref())
+        .inline_info(is_info_inline(&OPTIONS))
+        .tabstop(OPTIONS.tabstop.as_deref())
+        .header_lines(if OPTIONS.from_stdin {
+            OPTIONS.header_lines
+        } else {
+            0
+        })
         .build()
         .expect("Failed to build skim options")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiebreak_criteria_joined_in_order() {
+        let options = Cli::parse_from(["sklauncher", "--tiebreak", "score,begin,index"]);
+        assert_eq!(
+            join_tiebreak(&options.tiebreak),
+            Ok("score,begin,index".to_string())
+        );
+        let options = Cli::parse_from(["sklauncher", "--tiebreak", "end"]);
+        assert_eq!(join_tiebreak(&options.tiebreak), Ok("end".to_string()));
+    }
+
+    #[test]
+    fn duplicate_tiebreak_criterion_rejected() {
+        let options = Cli::parse_from(["sklauncher", "--tiebreak", "score,begin,score"]);
+        assert_eq!(
+            join_tiebreak(&options.tiebreak),
+            Err("tiebreak criterion 'score' appears more than once".to_string())
+        );
+    }
+
+    #[test]
+    fn rank_builder_puts_score_first_unless_given() {
+        let rank_builder = RankBuilder::new(&[Tiebreak::Begin, Tiebreak::End]);
+        assert_eq!(rank_builder.build_rank(10, 2, 5), [-10, 2, 5, 0]);
+        let rank_builder = RankBuilder::new(&[Tiebreak::End, Tiebreak::Score]);
+        assert_eq!(rank_builder.build_rank(10, 2, 5), [5, -10, 0, 0]);
+    }
+
+    #[test]
+    fn rank_builder_leaves_index_to_skim() {
+        let rank_builder = RankBuilder::new(&[Tiebreak::Index, Tiebreak::Begin]);
+        assert_eq!(rank_builder.build_rank(10, 2, 5), [-10, 2, 0, 0]);
+    }
+
+    #[test]
+    fn ranked_engine_applies_tiebreak() {
+        let factory = LiteralEngineFactory::new(
+            true,
+            FuzzyAlgorithm::SkimV2,
+            Arc::new(RankBuilder::new(&[Tiebreak::Score, Tiebreak::Begin])),
+        );
+        let engine = factory.create_engine_with_case("ab", CaseMatching::Ignore);
+        let rank = |text: &str| {
+            let item: Arc<dyn SkimItem> = Arc::new(text.to_string());
+            engine.match_item(item).unwrap().rank
+        };
+        // equal scores are ordered by the position of the match
+        assert_eq!(rank("ab-zz")[0], rank("zz-ab")[0]);
+        assert!(rank("ab-zz") < rank("zz-ab"));
+
+        // skim's default criteria would order them by the position as well
+        let factory = LiteralEngineFactory::new(
+            true,
+            FuzzyAlgorithm::SkimV2,
+            Arc::new(RankBuilder::new(&[Tiebreak::Score])),
+        );
+        let engine = factory.create_engine_with_case("ab", CaseMatching::Ignore);
+        let rank = |text: &str| {
+            let item: Arc<dyn SkimItem> = Arc::new(text.to_string());
+            engine.match_item(item).unwrap().rank
+        };
+        assert_eq!(rank("ab-zz"), rank("zz-ab"));
+    }
+
+    #[test]
+    fn literal_engine_ignores_extended_syntax() {
+        let factory = LiteralEngineFactory::new(
+            true,
+            FuzzyAlgorithm::SkimV2,
+            Arc::new(RankBuilder::new(&[Tiebreak::Score])),
+        );
+        let engine = factory.create_engine_with_case("^a|b$", CaseMatching::Ignore);
+        let item = |text: &str| -> Arc<dyn SkimItem> { Arc::new(text.to_string()) };
+        assert!(engine.match_item(item("x ^a|b$ y")).is_some());
+        assert!(engine.match_item(item("ab")).is_none());
+    }
+
+    #[test]
+    fn number_keys_bound_to_accept_nth_entry() {
+        let binds = get_number_binds(&Cli::parse_from(["sklauncher", "--numbered"]));
+        assert_eq!(binds.len(), 9);
+        assert_eq!(binds[0], "1:down(9999)+up(0)+accept");
+        assert_eq!(binds[8], "9:down(9999)+up(8)+accept");
+        // the first entry is at the top in reverse layouts
+        let binds = get_number_binds(&Cli::parse_from(["sklauncher", "--numbered", "--reverse"]));
+        assert_eq!(binds[0], "1:up(9999)+down(0)+accept");
+        assert_eq!(binds[8], "9:up(9999)+down(8)+accept");
+        assert!(get_number_binds(&Cli::parse_from(["sklauncher"])).is_empty());
+    }
+
+    #[test]
+    fn preview_window_by_position() {
+        let preview_window =
+            |args: &[&str]| get_preview_window(&Cli::parse_from([&["sklauncher"], args].concat()));
+        assert_eq!(preview_window(&[]), "right:50%:wrap");
+        for (position, side) in [
+            ("top", "up"),
+            ("bottom", "down"),
+            ("left", "left"),
+            ("right", "right"),
+        ] {
+            assert_eq!(
+                preview_window(&["--preview-position", position]),
+                format!("{}:50%:wrap", side)
+            );
+        }
+        // `--preview-window` overrides the position
+        assert_eq!(
+            preview_window(&["--preview-position", "top", "--preview-window", "left:30%"]),
+            "left:30%:wrap"
+        );
+    }
+
+    #[test]
+    fn preview_window_by_size_and_position() {
+        let preview_window =
+            |args: &[&str]| get_preview_window(&Cli::parse_from([&["sklauncher"], args].concat()));
+        assert_eq!(preview_window(&["--preview-size", "30%"]), "right:30%:wrap");
+        assert_eq!(
+            preview_window(&["--preview-position", "bottom", "--preview-size", "12"]),
+            "down:12:wrap"
+        );
+        // `--preview-window` overrides the size
+        assert_eq!(
+            preview_window(&["--preview-size", "30%", "--preview-window", "up:60%"]),
+            "up:60%:wrap"
+        );
+        assert!(Cli::try_parse_from(["sklauncher", "--preview-size", "120%"]).is_err());
+        assert!(Cli::try_parse_from(["sklauncher", "--preview-size", "half"]).is_err());
+    }
+
+    #[test]
+    fn preview_window_wrap_added_once_or_dropped() {
+        let preview_window =
+            |args: &[&str]| get_preview_window(&Cli::parse_from([&["sklauncher"], args].concat()));
+        assert_eq!(
+            preview_window(&["--preview-window", "up:40%:wrap:hidden"]),
+            "up:40%:wrap:hidden"
+        );
+        assert_eq!(
+            preview_window(&["--preview-position", "left", "--no-preview-wrap"]),
+            "left:50%"
+        );
+        assert_eq!(
+            preview_window(&["--preview-window", "up:wrap:hidden", "--no-preview-wrap"]),
+            "up:hidden"
+        );
+    }
+
+    #[test]
+    fn color_applied_on_top_of_theme() {
+        let color = |args: &[&str]| get_color(&Cli::parse_from([&["sklauncher"], args].concat()));
+        assert_eq!(color(&[]), None);
+        assert_eq!(
+            color(&["--theme", "nord"]).as_deref(),
+            Some(Theme::Nord.color())
+        );
+        // skim applies mappings in order, so the later `--color` wins
+        assert_eq!(
+            color(&["--theme", "nord", "--color", "hl:#ff0000"]),
+            Some(format!("{},hl:#ff0000", Theme::Nord.color()))
+        );
+        assert_eq!(
+            color(&["--color", "light,hl:#ff0000"]).as_deref(),
+            Some("light,hl:#ff0000")
+        );
+    }
+
+    #[test]
+    fn min_height_only_with_percent_height() {
+        let min_height =
+            |args: &[&str]| get_min_height(&Cli::parse_from([&["sklauncher"], args].concat()));
+        assert_eq!(min_height(&[]), "10");
+        assert_eq!(min_height(&["--min-height", "20"]), "20");
+        assert_eq!(min_height(&["--height", "40%", "--min-height", "20"]), "20");
+        assert_eq!(min_height(&["--height", "40%"]), "10");
+        // an absolute height is not clamped
+        assert_eq!(min_height(&["--height", "15", "--min-height", "20"]), "0");
+        assert_eq!(min_height(&["--height", "15"]), "0");
+    }
+
+    #[test]
+    fn info_style_parsed_and_hidden_shown_inline() {
+        let parse = |args: &[&str]| Cli::parse_from([&["sklauncher"], args].concat());
+        let options = parse(&[]);
+        assert!(options.info == Some(InfoStyle::Default));
+        assert!(!is_info_inline(&options));
+        let options = parse(&["--info", "inline"]);
+        assert!(options.info == Some(InfoStyle::Inline));
+        assert!(is_info_inline(&options));
+        let options = parse(&["--info=hidden"]);
+        assert!(options.info == Some(InfoStyle::Hidden));
+        assert!(is_info_inline(&options));
+        assert!(is_info_inline(&parse(&["--inline-info"])));
+        assert!(Cli::try_parse_from(["sklauncher", "--info", "none"]).is_err());
+    }
+}
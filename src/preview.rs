@@ -0,0 +1,121 @@
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+
+use crate::entry::OPTIONS;
+use crate::options::BinPreview;
+
+lazy_static! {
+    static ref RE_WHATIS: Regex = Regex::new(r"(?m)^.*?\s+-\s+").unwrap();
+}
+
+// Maximum bytes read from the output of a preview command
+const MAX_OUTPUT: u64 = 16 * 1024;
+
+/// Run command with null stdin and return its stdout if it succeeds.
+/// The output is capped to `MAX_OUTPUT` bytes, and invalid UTF-8 is replaced with U+FFFD.
+/// If the command doesn't finish within `--preview-timeout`, it is killed and the output
+/// collected so far is returned.
+pub fn run_with_timeout(cmd: &mut Command) -> Option<String> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    // read output in another thread so that a full pipe can't block the child
+    let stdout = child.stdout.take()?;
+    let reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        drop(stdout.take(MAX_OUTPUT).read_to_end(&mut buf));
+        buf
+    });
+
+    let deadline = Instant::now() + Duration::from_millis(OPTIONS.preview_timeout);
+    let success = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status.success(),
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            Ok(None) => {
+                // timed out, use partial output
+                drop(child.kill());
+                drop(child.wait());
+                break true;
+            }
+            Err(_) => {
+                drop(child.kill());
+                drop(child.wait());
+                break false;
+            }
+        }
+    };
+
+    let output = reader.join().ok()?;
+    if success && !output.is_empty() {
+        // descriptions may be in another encoding, or cut in the middle of a char by the cap
+        Some(String::from_utf8_lossy(&output).into_owned())
+    } else {
+        None
+    }
+}
+
+// Command looking up the description of a program. `--` ends options, so that a name starting
+// with `-` is not taken as an option.
+fn lookup_command(program: &str, options: &[&str], name: &str) -> Command {
+    let mut cmd = Command::new(program);
+    cmd.args(options).arg("--").arg(name);
+    cmd
+}
+
+fn whatis(path: &str) -> Option<String> {
+    let output = run_with_timeout(&mut lookup_command("whatis", &["--long"], path))?;
+    Some(RE_WHATIS.replace_all(&output, "").into_owned())
+}
+
+fn tldr(name: &str) -> Option<String> {
+    run_with_timeout(&mut lookup_command("tldr", &[], name))
+}
+
+fn help(path: &str) -> Option<String> {
+    run_with_timeout(Command::new(path).arg("--help"))
+}
+
+/// Description of a bin entry from the first source of `--bin-preview` which yields anything
+pub fn bin_description(path: &str, name: &str) -> Option<String> {
+    OPTIONS.bin_preview.iter().find_map(|source| {
+        let text = match source {
+            BinPreview::Whatis => whatis(path),
+            BinPreview::Tldr => tldr(name),
+            BinPreview::Help => help(path),
+        }?;
+        if text.trim().is_empty() {
+            None
+        } else {
+            Some(text)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_passed_after_end_of_options() {
+        let args = |cmd: Command| -> Vec<String> {
+            cmd.get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(
+            args(lookup_command("whatis", &["--long"], "-rf")),
+            ["--long", "--", "-rf"]
+        );
+        assert_eq!(args(lookup_command("tldr", &[], "--")), ["--", "--"]);
+        assert_eq!(args(lookup_command("tldr", &[], "tar")), ["--", "tar"]);
+    }
+}
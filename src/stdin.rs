@@ -0,0 +1,69 @@
+use std::io::{self, BufRead};
+use std::thread;
+
+use skim::prelude::*;
+
+use crate::entry::OPTIONS;
+use crate::options::get_max_results;
+
+// Line read from stdin, previewing a field of it for `--preview-field`
+struct StdinItem {
+    line: String,
+}
+
+impl SkimItem for StdinItem {
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.line)
+    }
+
+    fn preview(&self, _context: PreviewContext) -> ItemPreview {
+        let delimiter = OPTIONS.delimiter.as_deref().unwrap_or("\t");
+        let field = OPTIONS
+            .preview_field
+            .and_then(|n| self.line.split(delimiter).nth(n.get() - 1))
+            .unwrap_or("");
+        ItemPreview::Text(field.to_string())
+    }
+}
+
+// Number of lines read ahead of the finder, which bounds memory used for unread lines
+const STDIN_BUFFER_LINES: usize = 1024;
+
+/// Run finder over lines read from stdin and print the selected line to stdout.
+/// If nothing is selected, the query is printed instead.
+pub fn run_stdin(options: &SkimOptions) {
+    let (tx_item, rx_item): (SkimItemSender, SkimItemReceiver) = bounded(STDIN_BUFFER_LINES);
+    // lines are streamed to the finder while reading, so matching starts before EOF. the thread
+    // stops when the finder has exited and dropped the receiver, and is not waited for since
+    // stdin may never be closed.
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().take(get_max_results()) {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if tx_item.send(Arc::new(StdinItem { line })).is_err() {
+                break;
+            }
+        }
+    });
+
+    let output = Skim::run_with(options, Some(rx_item));
+
+    // error
+    if output.is_none() {
+        std::process::exit(135);
+    }
+
+    // aborted (maybe Esc key is pressed)
+    let output = output.unwrap();
+    if output.is_abort {
+        std::process::exit(130);
+    }
+
+    if output.selected_items.is_empty() {
+        println!("{}", output.query);
+    } else {
+        println!("{}", output.selected_items[0].output());
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::entry::{find_desktop_files, get_app_dirs};
+use crate::exec::resolve_command;
+
+// Field codes defined by the desktop entry spec, and deprecated ones which should be removed
+const FIELD_CODES: [char; 8] = ['f', 'F', 'u', 'U', 'i', 'c', 'k', '%'];
+const DEPRECATED_FIELD_CODES: [char; 6] = ['d', 'D', 'n', 'N', 'v', 'm'];
+
+#[derive(Default)]
+struct Report {
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+// Check field codes of an Exec value
+fn check_field_codes(exec: &str, report: &mut Report) {
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        match chars.next() {
+            Some(code) if FIELD_CODES.contains(&code) => {}
+            Some(code) if DEPRECATED_FIELD_CODES.contains(&code) => report
+                .warnings
+                .push(format!("Exec uses deprecated field code %{}", code)),
+            Some(code) => report
+                .errors
+                .push(format!("Exec uses invalid field code %{}", code)),
+            None => report
+                .errors
+                .push("Exec ends with an incomplete field code".to_string()),
+        }
+    }
+}
+
+fn check_exec(key: &str, exec: &str, report: &mut Report) {
+    match shlex::split(exec) {
+        Some(args) if !args.is_empty() => check_field_codes(exec, report),
+        Some(_) => report.errors.push(format!("{} is empty", key)),
+        None => report
+            .errors
+            .push(format!("{} can't be parsed: {}", key, exec)),
+    }
+}
+
+// Run checks of the desktop entry spec on a desktop file
+fn validate_file(file: &Path) -> Report {
+    let mut report = Report::default();
+    let conf = match ini::Ini::load_from_file(file) {
+        Ok(conf) => conf,
+        Err(err) => {
+            report.errors.push(format!("can't be parsed: {}", err));
+            return report;
+        }
+    };
+    let section = match conf.section(Some("Desktop Entry")) {
+        Some(section) => section,
+        None => {
+            report
+                .errors
+                .push("[Desktop Entry] group is missing".to_string());
+            return report;
+        }
+    };
+
+    if section.get("Name").is_none() {
+        report
+            .errors
+            .push("required key Name is missing".to_string());
+    }
+    match section.get("Type") {
+        Some("Application") => {
+            let dbus_activatable = section.get("DBusActivatable").is_some_and(|v| v == "true");
+            match section.get("Exec") {
+                Some(exec) => check_exec("Exec", exec, &mut report),
+                None if dbus_activatable => {}
+                None => report
+                    .errors
+                    .push("Exec is required for Type=Application".to_string()),
+            }
+        }
+        Some("Link") => {
+            if section.get("URL").is_none() {
+                report
+                    .errors
+                    .push("URL is required for Type=Link".to_string());
+            }
+        }
+        Some("Directory") => {}
+        Some(other) => report.errors.push(format!("invalid Type: {}", other)),
+        None => report
+            .errors
+            .push("required key Type is missing".to_string()),
+    }
+    if let Some(try_exec) = section.get("TryExec") {
+        if resolve_command(try_exec).is_none() {
+            report
+                .warnings
+                .push(format!("TryExec can't be resolved: {}", try_exec));
+        }
+    }
+    for id in section
+        .get("Actions")
+        .unwrap_or("")
+        .split(';')
+        .filter(|id| !id.is_empty())
+    {
+        match conf.section(Some(format!("Desktop Action {}", id))) {
+            Some(action) => {
+                if action.get("Name").is_none() {
+                    report
+                        .errors
+                        .push(format!("Name of action {} is missing", id));
+                }
+                if let Some(exec) = action.get("Exec") {
+                    check_exec(&format!("Exec of action {}", id), exec, &mut report);
+                }
+            }
+            None => report
+                .warnings
+                .push(format!("[Desktop Action {}] group is missing", id)),
+        }
+    }
+    report
+}
+
+/// Validate all desktop files in the application directories and print problems found.
+/// Returns false if any error was found.
+pub fn validate_desktop_files() -> bool {
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    // check also files usually skipped like ones under autostart directories
+    for dir in get_app_dirs() {
+        find_desktop_files(&dir, &mut visited, 0, true, &mut files);
+    }
+
+    let (mut errors, mut warnings) = (0, 0);
+    for file in files.iter() {
+        let report = validate_file(file);
+        for error in report.errors.iter() {
+            println!("{}: error: {}", file.display(), error);
+        }
+        for warning in report.warnings.iter() {
+            println!("{}: warning: {}", file.display(), warning);
+        }
+        errors += report.errors.len();
+        warnings += report.warnings.len();
+    }
+    println!(
+        "{} files checked, {} errors, {} warnings",
+        files.len(),
+        errors,
+        warnings
+    );
+    errors == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    // Validate a desktop file of the content, returning its errors and warnings
+    fn validate(name: &str, content: &str) -> (Vec<String>, Vec<String>) {
+        let file = env::temp_dir().join(format!(
+            "sklauncher-{}-{}.desktop",
+            std::process::id(),
+            name
+        ));
+        fs::write(&file, content).unwrap();
+        let report = validate_file(&file);
+        fs::remove_file(&file).unwrap();
+        (report.errors, report.warnings)
+    }
+
+    #[test]
+    fn valid_file_has_no_problems() {
+        let (errors, warnings) = validate(
+            "valid",
+            "[Desktop Entry]\nType=Application\nName=App\nExec=app %U\nActions=new;\n\
+             [Desktop Action new]\nName=New\nExec=app --new\n",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn missing_exec_is_error() {
+        let (errors, _) = validate("no-exec", "[Desktop Entry]\nType=Application\nName=App\n");
+        assert_eq!(errors, ["Exec is required for Type=Application"]);
+        // D-Bus activatable applications don't need Exec
+        let (errors, _) = validate(
+            "dbus",
+            "[Desktop Entry]\nType=Application\nName=App\nDBusActivatable=true\n",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn field_codes_checked() {
+        let (errors, warnings) = validate(
+            "codes",
+            "[Desktop Entry]\nType=Application\nName=App\nExec=app %x %d %\n",
+        );
+        assert_eq!(
+            errors,
+            [
+                "Exec uses invalid field code %x",
+                "Exec ends with an incomplete field code"
+            ]
+        );
+        assert_eq!(warnings, ["Exec uses deprecated field code %d"]);
+    }
+
+    #[test]
+    fn unknown_type_is_error() {
+        let (errors, _) = validate("type", "[Desktop Entry]\nType=Program\nName=App\n");
+        assert_eq!(errors, ["invalid Type: Program"]);
+        let (errors, _) = validate("no-type", "[Desktop Entry]\nExec=app\n");
+        assert_eq!(
+            errors,
+            [
+                "required key Name is missing",
+                "required key Type is missing"
+            ]
+        );
+    }
+}